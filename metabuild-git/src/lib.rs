@@ -1,12 +1,37 @@
 use auth_git2::GitAuthenticator;
 use git2::{Config, FetchOptions, PushOptions, RemoteCallbacks};
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
 
+/// Username which is used for tokens that are configured without an explicit username
+const DEFAULT_TOKEN_USERNAME: &str = "oauth2";
+
+fn tokens() -> &'static Mutex<HashMap<String, String>> {
+    static TOKENS: OnceLock<Mutex<HashMap<String, String>>> = OnceLock::new();
+    TOKENS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Registers an access token for HTTPS remotes on the given host. The token can be given as
+/// `username:token`, otherwise the username defaults to `oauth2`.
+pub fn add_token(host: &str, token: &str) {
+    tokens()
+        .lock()
+        .expect("Failed to lock git token registry")
+        .insert(host.to_string(), token.to_string());
+}
+
+/// Returns the user's git configuration so that credential helpers are available
 pub fn make_git_config() -> Result<Config, git2::Error> {
-    Config::new()
+    Config::open_default().or_else(|_| Config::new())
 }
 
 pub fn make_git_authenticator() -> GitAuthenticator {
-    GitAuthenticator::default()
+    let mut authenticator = GitAuthenticator::default();
+    for (host, token) in tokens().lock().expect("Failed to lock git token registry").iter() {
+        let (username, password) = token.split_once(':').unwrap_or((DEFAULT_TOKEN_USERNAME, token));
+        authenticator = authenticator.add_plaintext_credentials(host, username, password);
+    }
+    authenticator
 }
 
 pub fn make_fetch_options<'a>(auth: &'a GitAuthenticator, config: &'a Config) -> FetchOptions<'a> {
@@ -23,4 +48,4 @@ pub fn make_push_options<'a>(auth: &'a GitAuthenticator, config: &'a Config) ->
     remote_callbacks.credentials(auth.credentials(&config));
     push_options.remote_callbacks(remote_callbacks);
     push_options
-}
\ No newline at end of file
+}
@@ -0,0 +1,231 @@
+use anyhow::Error;
+use log::debug;
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use tempfile::NamedTempFile;
+
+/// Name of the file which marks the last use of a cached git repository
+const LAST_USED_FILE: &str = "mb-last-used";
+
+/// User-level package cache which is shared between projects
+///
+/// Git repositories are stored as bare clones below `git/<digest of url>`. Downloaded files
+/// (package tarballs, manifests) are stored as blobs below `blobs/<sha256 of content>`, and
+/// `refs/<digest of url>` records which blob was downloaded from which url.
+pub struct Cache {
+    root: PathBuf,
+}
+
+#[derive(Default)]
+pub struct CacheUsage {
+    pub entries: usize,
+    pub size: u64,
+}
+
+#[derive(Default)]
+pub struct CacheInfo {
+    pub git: CacheUsage,
+    pub blobs: CacheUsage,
+}
+
+pub fn sha256_digest(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+pub fn sha256_file(path: &Path) -> Result<String, Error> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+fn directory_size(path: &Path) -> u64 {
+    let Ok(entries) = std::fs::read_dir(path) else {
+        return 0;
+    };
+
+    entries
+        .filter_map(Result::ok)
+        .map(|e| match e.metadata() {
+            Ok(m) if m.is_dir() => directory_size(&e.path()),
+            Ok(m) => m.len(),
+            Err(_) => 0,
+        })
+        .sum()
+}
+
+fn touch(path: &Path) {
+    if let Ok(file) = File::options().write(true).open(path) {
+        let _ = file.set_modified(SystemTime::now());
+    }
+}
+
+fn last_modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+impl Cache {
+    pub fn new(root: &Path) -> Result<Self, Error> {
+        for dir in ["git", "blobs", "refs", "tmp"] {
+            std::fs::create_dir_all(root.join(dir))?;
+        }
+        debug!("Using package cache in {:?}", root);
+        Ok(Self { root: root.to_path_buf() })
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Returns the location of the bare repository for the given url
+    pub fn git_path(&self, url: &str) -> PathBuf {
+        self.root.join("git").join(sha256_digest(url.as_bytes()))
+    }
+
+    /// Marks the bare repository for the given url as used, `prune` keeps recently used repositories
+    pub fn mark_git_used(&self, url: &str) {
+        let path = self.git_path(url);
+        if path.exists() {
+            let _ = std::fs::write(path.join(LAST_USED_FILE), url);
+        }
+    }
+
+    /// Returns the path of the blob with the given digest if it is cached
+    pub fn get_blob(&self, digest: &str) -> Option<PathBuf> {
+        let path = self.root.join("blobs").join(digest);
+        if path.is_file() {
+            touch(&path);
+            Some(path)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the digest of the blob which was last downloaded from the given url
+    pub fn lookup(&self, url: &str) -> Option<String> {
+        let content = std::fs::read_to_string(self.root.join("refs").join(sha256_digest(url.as_bytes()))).ok()?;
+        let digest = content.trim().to_string();
+        self.get_blob(&digest).map(|_| digest)
+    }
+
+    /// Stores the file produced by `download` as a blob, records that it originates from
+    /// `url` and returns its digest
+    pub fn store<F>(&self, url: &str, download: F) -> Result<String, Error>
+    where
+        F: FnOnce(&Path) -> Result<(), Error>,
+    {
+        let temp_file = NamedTempFile::new_in(self.root.join("tmp"))?;
+        download(temp_file.path())?;
+        let digest = sha256_file(temp_file.path())?;
+        let blob_path = self.root.join("blobs").join(&digest);
+        if blob_path.exists() {
+            touch(&blob_path);
+        } else {
+            temp_file.persist(&blob_path)?;
+        }
+        std::fs::write(self.root.join("refs").join(sha256_digest(url.as_bytes())), &digest)?;
+        debug!("Stored {url} in cache as {digest}");
+        Ok(digest)
+    }
+
+    pub fn info(&self) -> Result<CacheInfo, Error> {
+        let mut info = CacheInfo::default();
+        for entry in std::fs::read_dir(self.root.join("git"))? {
+            info.git.entries += 1;
+            info.git.size += directory_size(&entry?.path());
+        }
+        for entry in std::fs::read_dir(self.root.join("blobs"))? {
+            info.blobs.entries += 1;
+            info.blobs.size += entry?.metadata()?.len();
+        }
+        Ok(info)
+    }
+
+    /// Removes all cached data
+    pub fn clean(&self) -> Result<(), Error> {
+        for dir in ["git", "blobs", "refs", "tmp"] {
+            std::fs::remove_dir_all(self.root.join(dir))?;
+            std::fs::create_dir_all(self.root.join(dir))?;
+        }
+        Ok(())
+    }
+
+    /// Removes all entries which have not been used within `max_age` and returns their number
+    pub fn prune(&self, max_age: Duration) -> Result<usize, Error> {
+        let threshold = SystemTime::now() - max_age;
+        let mut removed = 0;
+
+        for entry in std::fs::read_dir(self.root.join("git"))? {
+            let path = entry?.path();
+            let last_used = last_modified(&path.join(LAST_USED_FILE)).or_else(|| last_modified(&path));
+            if last_used.is_none_or(|t| t < threshold) {
+                debug!("Pruning git repository {:?}", path);
+                std::fs::remove_dir_all(&path)?;
+                removed += 1;
+            }
+        }
+
+        for entry in std::fs::read_dir(self.root.join("blobs"))? {
+            let path = entry?.path();
+            if last_modified(&path).is_none_or(|t| t < threshold) {
+                debug!("Pruning blob {:?}", path);
+                std::fs::remove_file(&path)?;
+                removed += 1;
+            }
+        }
+
+        // Drop refs pointing to blobs which no longer exist
+        for entry in std::fs::read_dir(self.root.join("refs"))? {
+            let path = entry?.path();
+            let digest = std::fs::read_to_string(&path).unwrap_or_default();
+            if !self.root.join("blobs").join(digest.trim()).is_file() {
+                std::fs::remove_file(&path)?;
+            }
+        }
+
+        Ok(removed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_store_and_lookup() -> Result<(), Error> {
+        let temp_dir = tempfile::tempdir()?;
+        let cache = Cache::new(temp_dir.path())?;
+        let url = "https://example.com/package.tar.gz";
+        assert!(cache.lookup(url).is_none());
+
+        let digest = cache.store(url, |path| Ok(std::fs::write(path, b"content")?))?;
+        assert_eq!(digest, sha256_digest(b"content"));
+        assert_eq!(cache.lookup(url), Some(digest.clone()));
+        assert!(cache.get_blob(&digest).is_some());
+
+        assert_eq!(cache.prune(Duration::from_secs(3600))?, 0);
+        assert_eq!(cache.prune(Duration::ZERO)?, 1);
+        assert!(cache.lookup(url).is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_mark_git_used() -> Result<(), Error> {
+        let temp_dir = tempfile::tempdir()?;
+        let cache = Cache::new(temp_dir.path())?;
+        let url = "https://example.com/package.git";
+        let path = cache.git_path(url);
+        std::fs::create_dir_all(&path)?;
+
+        // Looking up the path does not count as a use
+        cache.git_path(url);
+        assert!(!path.join(LAST_USED_FILE).exists());
+
+        cache.mark_git_used(url);
+        assert_eq!(std::fs::read_to_string(path.join(LAST_USED_FILE))?, url);
+        Ok(())
+    }
+}
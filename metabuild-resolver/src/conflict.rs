@@ -0,0 +1,232 @@
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::{Display, Formatter};
+
+use crate::inventory::Inventory;
+use crate::package::{Version, VersionReq};
+
+/// Maximum number of rejected versions per package which are shown in the text output
+const MAX_DISPLAYED_VERSIONS: usize = 10;
+
+/// Explanation why the dependencies could not be resolved
+#[derive(Debug, Clone, Serialize)]
+pub struct ConflictReport {
+    /// Root requirements which cannot be satisfied together
+    pub requirements: BTreeMap<String, semver::VersionReq>,
+    /// Packages for which no version satisfies all requirements
+    pub conflicts: Vec<PackageConflict>,
+    /// Explanation of the solver, used if no conflicting package could be determined
+    pub details: String,
+}
+
+/// Package version on the way from the root manifest to a requirement
+#[derive(Debug, Clone, Serialize)]
+pub struct PathNode {
+    pub package: String,
+    pub version: semver::Version,
+    /// Requirement through which the package was reached
+    pub requirement: VersionReq,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RequirementChain {
+    /// Packages leading from the root manifest to the requirement, empty if the root manifest
+    /// has the requirement itself
+    pub path: Vec<PathNode>,
+    pub requirement: VersionReq,
+    /// Optional dependencies and constraints only restrict the version
+    pub optional: bool,
+}
+
+impl RequirementChain {
+    fn matches(&self, version: &semver::Version) -> bool {
+        self.requirement.matches(&Version(version.clone()))
+    }
+
+    /// Returns the package which has the requirement
+    fn source(&self) -> String {
+        match self.path.last() {
+            Some(node) => format!("{} {}", node.package, node.version),
+            None => "the manifest".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RejectedVersion {
+    pub version: semver::Version,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PackageConflict {
+    pub package: String,
+    /// All requirements on the package and how they are reached
+    pub chains: Vec<RequirementChain>,
+    /// Known versions of the package in descending order
+    pub rejected: Vec<RejectedVersion>,
+}
+
+/// Collects the requirements on every package by following the dependencies of the newest
+/// version which is compatible with the requirements seen so far, like the solver tries first
+struct Explorer<'a, 'b> {
+    inventory: &'a Inventory<'b>,
+    chains: BTreeMap<String, Vec<RequirementChain>>,
+    selected: HashMap<String, semver::Version>,
+    visited: HashSet<(String, semver::Version)>,
+}
+
+impl Explorer<'_, '_> {
+    fn visit(&mut self, path: &[PathNode], name: &str, requirement: &VersionReq, optional: bool) {
+        let chains = self.chains.entry(name.to_string()).or_default();
+        chains.push(RequirementChain { path: path.to_vec(), requirement: requirement.clone(), optional });
+        if optional {
+            return;
+        }
+
+        let version = match self.selected.get(name) {
+            Some(version) => version.clone(),
+            None => {
+                let chains = &self.chains[name];
+                let Some((version, _)) = self
+                    .inventory
+                    .candidate_versions(name)
+                    .into_iter()
+                    .find(|(version, reason)| reason.is_none() && chains.iter().all(|c| c.matches(version)))
+                else {
+                    return;
+                };
+                self.selected.insert(name.to_string(), version.clone());
+                version
+            }
+        };
+        if !requirement.matches(&Version(version.clone())) || !self.visited.insert((name.to_string(), version.clone())) {
+            return;
+        }
+
+        let Ok(package) = self.inventory.get_package(name, &version) else {
+            return;
+        };
+        let dependencies: Vec<_> = package
+            .dependencies
+            .iter()
+            .map(|(dep_name, dep)| {
+                (dep_name.clone(), self.inventory.version_req(dep_name, &dep.version, dep.pre), dep.optional)
+            })
+            .chain(
+                package
+                    .constraints
+                    .iter()
+                    .map(|(dep_name, req)| (dep_name.clone(), self.inventory.version_req(dep_name, req, false), true)),
+            )
+            .collect();
+        let mut path = path.to_vec();
+        path.push(PathNode { package: name.to_string(), version, requirement: requirement.clone() });
+        for (dep_name, dep_requirement, dep_optional) in dependencies {
+            self.visit(&path, &dep_name, &dep_requirement, dep_optional);
+        }
+    }
+}
+
+/// Builds the conflict report for root requirements which the solver could not resolve.
+/// `is_solvable` is used to reduce the root requirements to a minimal conflicting set.
+pub(crate) fn analyze<F>(
+    inventory: &Inventory,
+    dependencies: &HashMap<String, semver::VersionReq>,
+    details: String,
+    is_solvable: F,
+) -> ConflictReport
+where
+    F: Fn(&HashMap<String, semver::VersionReq>) -> bool,
+{
+    let mut requirements: BTreeMap<_, _> = dependencies.clone().into_iter().collect();
+    let names: Vec<_> = requirements.keys().cloned().collect();
+    for name in names {
+        let mut reduced: HashMap<_, _> = requirements.clone().into_iter().collect();
+        reduced.remove(&name);
+        if !is_solvable(&reduced) {
+            requirements.remove(&name);
+        }
+    }
+
+    let mut explorer = Explorer {
+        inventory,
+        chains: BTreeMap::new(),
+        selected: HashMap::new(),
+        visited: HashSet::new(),
+    };
+    for (name, requirement) in &requirements {
+        explorer.visit(&[], name, &inventory.version_req(name, requirement, false), false);
+    }
+
+    let mut conflicts = Vec::new();
+    for (name, chains) in explorer.chains {
+        if chains.iter().all(|c| c.optional) {
+            continue;
+        }
+
+        let versions = inventory.candidate_versions(&name);
+        let satisfiable = versions
+            .iter()
+            .any(|(version, reason)| reason.is_none() && chains.iter().all(|c| c.matches(version)));
+        if satisfiable {
+            continue;
+        }
+
+        let rejected = versions
+            .into_iter()
+            .map(|(version, reason)| {
+                let reason = reason.unwrap_or_else(|| {
+                    chains
+                        .iter()
+                        .find(|c| !c.matches(&version))
+                        .map(|c| format!("does not match {} required by {}", c.requirement, c.source()))
+                        .unwrap_or_default()
+                });
+                RejectedVersion { version, reason }
+            })
+            .collect();
+        conflicts.push(PackageConflict { package: name, chains, rejected });
+    }
+
+    ConflictReport { requirements, conflicts, details }
+}
+
+impl Display for ConflictReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.conflicts.is_empty() {
+            return write!(f, "{}", self.details);
+        }
+
+        let requirements: Vec<_> = self.requirements.iter().map(|(name, req)| format!("{name} {req}")).collect();
+        writeln!(f, "The requirements {} cannot be satisfied together", requirements.join(", "))?;
+        for conflict in &self.conflicts {
+            writeln!(f, "No version of {} satisfies all requirements:", conflict.package)?;
+            for chain in &conflict.chains {
+                let mut source = "the manifest".to_string();
+                let mut indent = "  - ".to_string();
+                for node in &chain.path {
+                    writeln!(f, "{indent}{source} requires {} {}", node.package, node.requirement)?;
+                    source = format!("{} {}", node.package, node.version);
+                    indent = format!("{}└─ ", " ".repeat(indent.chars().count()));
+                }
+                let verb = if chain.optional { "restricts" } else { "requires" };
+                writeln!(f, "{indent}{source} {verb} {} {}", conflict.package, chain.requirement)?;
+            }
+
+            if conflict.rejected.is_empty() {
+                writeln!(f, "  No versions of {} are available", conflict.package)?;
+                continue;
+            }
+            writeln!(f, "  Rejected versions of {}:", conflict.package)?;
+            for rejected in conflict.rejected.iter().take(MAX_DISPLAYED_VERSIONS) {
+                writeln!(f, "    {}: {}", rejected.version, rejected.reason)?;
+            }
+            if conflict.rejected.len() > MAX_DISPLAYED_VERSIONS {
+                writeln!(f, "    ... and {} older versions", conflict.rejected.len() - MAX_DISPLAYED_VERSIONS)?;
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -2,7 +2,11 @@ use crate::repository::{BareRepository, RefType};
 use anyhow::Error;
 use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
-use std::path::Path;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Name of the registry which is used if a dependency does not specify one
+pub const DEFAULT_REGISTRY: &str = "default";
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "lowercase")]
@@ -14,13 +18,89 @@ pub enum Entry {
         server: String,
         repo: String,
         path: String,
+    },
+    /// Package files on a static web server
+    Http {
+        /// URL of the directory with the files of a version. The placeholder `{version}` is
+        /// replaced by the version, without it the version is appended as the last path segment.
+        url: String,
+        #[serde(default)]
+        listing: Listing,
+    },
+    /// Directory with one subdirectory per version, given as a path or `file://` URL
+    Local {
+        path: String,
+    },
+}
+
+/// How the available versions of an `Entry::Http` package are listed by the server
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Listing {
+    /// A `versions.json` file with an array of version strings
+    #[default]
+    Json,
+    /// An HTML directory listing with links to the version directories
+    Html,
+}
+
+const VERSION_PLACEHOLDER: &str = "{version}";
+
+/// Returns the directory of an `Entry::Local` package
+pub fn local_path(path: &str) -> PathBuf {
+    PathBuf::from(path.strip_prefix("file://").unwrap_or(path))
+}
+
+/// Returns the URL of the directory which contains the files of `version`
+pub fn http_version_url(url: &str, version: &str) -> String {
+    if url.contains(VERSION_PLACEHOLDER) {
+        url.replace(VERSION_PLACEHOLDER, version)
+    } else {
+        format!("{}/{version}", url.trim_end_matches('/'))
     }
 }
 
+/// Returns the URL of the directory which contains the version directories, i.e. the location
+/// of `versions.json` or of the HTML listing
+pub fn http_listing_url(url: &str) -> String {
+    match url.find(VERSION_PLACEHOLDER) {
+        Some(position) => {
+            let base = &url[..position];
+            base[..base.rfind('/').map_or(0, |p| p + 1)].to_string()
+        },
+        None => format!("{}/", url.trim_end_matches('/')),
+    }
+}
+
+/// Metadata of a single released version of a package
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct VersionMetadata {
+    /// Yanked versions are not selected for new resolutions
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub yanked: bool,
+    /// Deprecation message shown when the version is installed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deprecated: Option<String>,
+}
+
+impl VersionMetadata {
+    fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct IndexEntry {
+    #[serde(flatten)]
+    entry: Entry,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    versions: BTreeMap<semver::Version, VersionMetadata>,
+}
+
 pub struct Index {
     repo: BareRepository,
     reftype: RefType,
-    data: IndexMap<String, Entry>,
+    data: IndexMap<String, IndexEntry>,
 }
 
 impl Index {
@@ -32,6 +112,15 @@ impl Index {
         Ok(Self { repo, reftype, data })
     }
 
+    /// Opens the index from an existing local copy without contacting the remote
+    pub fn open_local(url: &str, branch: &str, storage_path: &Path) -> Result<Self, Error> {
+        let repo = BareRepository::open(url, storage_path)?;
+        let reftype = RefType::Branch(branch.to_string());
+        let index_contents = repo.get_file(&reftype, Path::new("index.json"))?;
+        let data = serde_json::from_str(&String::from_utf8_lossy(&index_contents))?;
+        Ok(Self { repo, reftype, data })
+    }
+
     pub fn get_entries(&self) -> Result<Vec<&str>, Error> {
         Ok(self.data.keys().map(|k| k.as_str()).collect())
     }
@@ -39,11 +128,33 @@ impl Index {
     pub fn get_entry(&self, name: &str) -> Result<&Entry, Error> {
         self.data
             .get(name)
+            .map(|e| &e.entry)
             .ok_or(anyhow::anyhow!("No index entry found for '{name}'"))
     }
 
+    /// Returns the metadata recorded for a version, if any
+    pub fn get_version_metadata(&self, name: &str, version: &semver::Version) -> Option<&VersionMetadata> {
+        self.data.get(name).and_then(|e| e.versions.get(version))
+    }
+
+    /// Adds or replaces an entry, metadata of existing versions is kept
     pub fn add_entry(&mut self, name: &str, entry: Entry) -> Result<(), Error> {
-        self.data.insert(name.to_string(), entry);
+        let versions = self.data.shift_remove(name).map(|e| e.versions).unwrap_or_default();
+        self.data.insert(name.to_string(), IndexEntry { entry, versions });
+        self.save_index()
+    }
+
+    /// Marks a version as yanked or removes the mark again
+    pub fn set_yanked(&mut self, name: &str, version: &semver::Version, yanked: bool) -> Result<(), Error> {
+        let index_entry = self
+            .data
+            .get_mut(name)
+            .ok_or(anyhow::anyhow!("No index entry found for '{name}'"))?;
+        let metadata = index_entry.versions.entry(version.clone()).or_default();
+        metadata.yanked = yanked;
+        if metadata.is_empty() {
+            index_entry.versions.remove(version);
+        }
         self.save_index()
     }
 
@@ -66,6 +177,59 @@ impl Index {
     }
 }
 
+/// Named indexes of a project, the packages of all of them are available for resolving
+#[derive(Default)]
+pub struct Registries {
+    indexes: IndexMap<String, Index>,
+}
+
+impl Registries {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, name: &str, index: Index) {
+        self.indexes.insert(name.to_string(), index);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Index> {
+        self.indexes.get(name)
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.indexes.keys().map(String::as_str).collect()
+    }
+
+    /// Returns the names of the registries which have an entry for `package`
+    pub fn find(&self, package: &str) -> Vec<&str> {
+        self.indexes
+            .iter()
+            .filter(|(_, index)| index.data.contains_key(package))
+            .map(|(name, _)| name.as_str())
+            .collect()
+    }
+
+    /// Returns the names of all entries of all registries without duplicates
+    pub fn get_entries(&self) -> Vec<&str> {
+        let mut entries = IndexMap::new();
+        for index in self.indexes.values() {
+            for name in index.data.keys() {
+                entries.insert(name.as_str(), ());
+            }
+        }
+        entries.into_keys().collect()
+    }
+}
+
+impl From<Index> for Registries {
+    /// Uses the index as the only registry
+    fn from(index: Index) -> Self {
+        let mut registries = Self::new();
+        registries.add(DEFAULT_REGISTRY, index);
+        registries
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -76,18 +240,68 @@ mod tests {
         let temp_dir = tempdir().unwrap();
         let index = Index::new(
             "https://github.com/jasal82/index.git",
-            &RefType::Branch(String::from("main")),
+            "main",
             temp_dir.path(),
         )
         .unwrap();
-        println!("modules: {:?}", index.get_packages().unwrap());
-        assert_eq!(
-            index.get_module_url("module1").unwrap(),
-            "https://github.com/jasal82/module1.git"
-        );
-        assert_eq!(
-            index.get_module_url("module2").unwrap(),
-            "https://github.com/jasal82/module2.git"
-        );
+        println!("modules: {:?}", index.get_entries().unwrap());
+        assert!(matches!(
+            index.get_entry("module1").unwrap(),
+            Entry::Git { url } if url == "https://github.com/jasal82/module1.git"
+        ));
+        assert!(matches!(
+            index.get_entry("module2").unwrap(),
+            Entry::Git { url } if url == "https://github.com/jasal82/module2.git"
+        ));
+    }
+
+    #[test]
+    fn test_http_urls() {
+        let url = "https://files.example.com/packages/core";
+        assert_eq!(http_version_url(url, "1.0.0"), "https://files.example.com/packages/core/1.0.0");
+        assert_eq!(http_listing_url(url), "https://files.example.com/packages/core/");
+        assert_eq!(http_listing_url(&format!("{url}/")), "https://files.example.com/packages/core/");
+
+        let url = "https://files.example.com/core/v{version}/dist";
+        assert_eq!(http_version_url(url, "1.0.0"), "https://files.example.com/core/v1.0.0/dist");
+        assert_eq!(http_listing_url(url), "https://files.example.com/core/");
+    }
+
+    #[test]
+    fn test_local_path() {
+        assert_eq!(local_path("/srv/packages/core"), PathBuf::from("/srv/packages/core"));
+        assert_eq!(local_path("file:///srv/packages/core"), PathBuf::from("/srv/packages/core"));
+    }
+
+    #[test]
+    fn test_version_metadata() -> Result<(), Error> {
+        let data: IndexMap<String, IndexEntry> = serde_json::from_str(
+            r#"{
+                "module1": { "type": "git", "url": "https://example.com/module1.git" },
+                "module3": { "type": "http", "url": "https://example.com/module3" },
+                "module2": {
+                    "type": "git",
+                    "url": "https://example.com/module2.git",
+                    "versions": {
+                        "1.0.0": { "yanked": true },
+                        "1.1.0": { "deprecated": "Use 2.x instead" }
+                    }
+                }
+            }"#,
+        )?;
+
+        assert!(data["module1"].versions.is_empty());
+        assert!(matches!(&data["module3"].entry, Entry::Http { listing: Listing::Json, .. }));
+        assert!(matches!(&data["module2"].entry, Entry::Git { url } if url == "https://example.com/module2.git"));
+        let versions = &data["module2"].versions;
+        assert!(versions[&semver::Version::new(1, 0, 0)].yanked);
+        assert!(!versions[&semver::Version::new(1, 1, 0)].yanked);
+        assert_eq!(versions[&semver::Version::new(1, 1, 0)].deprecated.as_deref(), Some("Use 2.x instead"));
+
+        // Entries without version metadata keep their previous format
+        let serialized = serde_json::to_value(&data)?;
+        assert_eq!(serialized["module1"], serde_json::json!({ "type": "git", "url": "https://example.com/module1.git" }));
+        assert_eq!(serialized["module2"]["versions"]["1.0.0"], serde_json::json!({ "yanked": true }));
+        Ok(())
     }
 }
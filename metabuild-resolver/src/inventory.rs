@@ -7,18 +7,21 @@ use resolvo::{
 };
 use serde::Deserialize;
 use serde_json;
-use std::collections::HashMap;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::path::{Path, PathBuf};
 use std::rc::Rc;
 use std::str::FromStr;
 use toml::Value;
 use ureq;
 
-use crate::index::{Index, Entry};
+use crate::cache::Cache;
+use crate::index::{http_listing_url, http_version_url, local_path, Entry, Index, Listing, Registries, VersionMetadata, DEFAULT_REGISTRY};
+use crate::manifest::{parse_constraints, parse_dependencies};
 use crate::package::{Package, Version, VersionReq};
 use crate::repository::{BareRepository, RefType};
 
-trait MetadataRetriever {
+pub trait MetadataRetriever {
     fn fetch_versions(&self) -> Result<Vec<String>, Error>;
     fn fetch_package_manifest(&self, version: &str) -> Result<Value, Error>;
 }
@@ -27,8 +30,14 @@ struct GitMetadataRetriever {
     repo: BareRepository,
 }
 impl GitMetadataRetriever {
-    fn new(name: &str, url: &str, cache_path: &Path) -> Result<Self, Error> {
-        let repo = BareRepository::new(url, Some(cache_path.join(name).as_path()))?;
+    fn new(url: &str, cache: &Cache) -> Result<Self, Error> {
+        let repo = BareRepository::new(url, Some(cache.git_path(url).as_path()))?;
+        cache.mark_git_used(url);
+        Ok(Self { repo })
+    }
+
+    fn open_cached(url: &str, cache: &Cache) -> Result<Self, Error> {
+        let repo = BareRepository::open(url, cache.git_path(url).as_path())?;
         Ok(Self { repo })
     }
 }
@@ -58,7 +67,7 @@ struct FileInfo {
     name: String,
 }
 
-struct ArtifactoryMetadataRetriever {
+pub struct ArtifactoryMetadataRetriever {
     server: String,
     repo: String,
     path: String,
@@ -66,7 +75,7 @@ struct ArtifactoryMetadataRetriever {
 }
 
 impl ArtifactoryMetadataRetriever {
-    fn new(server: &str, repo: &str, path: &str, token: Option<&str>) -> Self {
+    pub fn new(server: &str, repo: &str, path: &str, token: Option<&str>) -> Self {
         Self { server: server.to_string(), repo: repo.to_string(), path: path.to_string(), token: token.map(String::from) }
     }
 }
@@ -152,81 +161,500 @@ impl MetadataRetriever for ArtifactoryMetadataRetriever {
     }
 }
 
+/// Retrieves the metadata of packages which are served by a static web server
+pub struct HttpMetadataRetriever {
+    url: String,
+    listing: Listing,
+    token: Option<String>,
+}
+
+impl HttpMetadataRetriever {
+    pub fn new(url: &str, listing: Listing, token: Option<&str>) -> Self {
+        Self { url: url.to_string(), listing, token: token.map(String::from) }
+    }
+
+    fn get(&self, url: &str) -> Result<String, Error> {
+        let mut request = ureq::get(url);
+        if let Some(token) = self.token.as_ref() {
+            debug!("Using token for authentication");
+            request = request.set("Authorization", format!("Bearer {token}").as_str());
+        }
+
+        match request.call() {
+            Ok(response) => Ok(response.into_string()?),
+            Err(ureq::Error::Status(code, response)) => {
+                Err(anyhow::anyhow!("Server returned code {code} for {url}: {}", response.status_text()))
+            },
+            Err(e) => Err(anyhow::anyhow!("Could not fetch {url}: {e}")),
+        }
+    }
+
+    /// Extracts the versions from the links of an HTML directory listing. Links which do not
+    /// point to a version directory, e.g. to the parent directory, are skipped.
+    fn parse_listing(&self, html: &str) -> Vec<String> {
+        // Path segment of the version directory around the placeholder, e.g. `v` and `` for `v{version}`
+        let (prefix, suffix) = match self.url.split_once("{version}") {
+            Some((before, after)) => (
+                before.rsplit('/').next().unwrap_or_default(),
+                after.split('/').next().unwrap_or_default(),
+            ),
+            None => ("", ""),
+        };
+
+        let mut versions = Vec::new();
+        for link in html.split("href=").skip(1) {
+            let Some(quote) = link.chars().next().filter(|c| *c == '"' || *c == '\'') else {
+                continue;
+            };
+            let Some(target) = link[1..].split(quote).next() else {
+                continue;
+            };
+            let name = target.split(['?', '#']).next().unwrap_or_default().trim_end_matches('/');
+            let name = name.rsplit('/').next().unwrap_or_default();
+            let Some(version) = name.strip_prefix(prefix).and_then(|n| n.strip_suffix(suffix)) else {
+                continue;
+            };
+            if semver::Version::parse(version).is_ok() {
+                versions.push(version.to_string());
+            } else {
+                debug!("Skipping link {target} which is not a version");
+            }
+        }
+
+        versions
+    }
+}
+
+impl MetadataRetriever for HttpMetadataRetriever {
+    fn fetch_versions(&self) -> Result<Vec<String>, Error> {
+        let listing_url = http_listing_url(&self.url);
+        match self.listing {
+            Listing::Json => {
+                let url = format!("{listing_url}versions.json");
+                debug!("Fetching versions from {url}");
+                serde_json::from_str(&self.get(&url)?)
+                    .map_err(|e| anyhow::anyhow!("Invalid version list {url}: {e}"))
+            },
+            Listing::Html => {
+                debug!("Fetching directory listing from {listing_url}");
+                Ok(self.parse_listing(&self.get(&listing_url)?))
+            },
+        }
+    }
+
+    fn fetch_package_manifest(&self, version: &str) -> Result<Value, Error> {
+        let url = format!("{}/manifest.toml", http_version_url(&self.url, version));
+        debug!("Fetching package manifest from {url}");
+        toml::from_str(&self.get(&url)?).map_err(|e| anyhow::anyhow!("Failed to parse manifest: {e}"))
+    }
+}
+
+/// Retrieves the metadata of packages from a local directory with one subdirectory per version
+pub struct LocalMetadataRetriever {
+    path: PathBuf,
+}
+
+impl LocalMetadataRetriever {
+    pub fn new(path: &Path) -> Self {
+        Self { path: path.to_path_buf() }
+    }
+}
+
+impl MetadataRetriever for LocalMetadataRetriever {
+    fn fetch_versions(&self) -> Result<Vec<String>, Error> {
+        let entries = std::fs::read_dir(&self.path)
+            .map_err(|e| anyhow::anyhow!("Could not read package directory {}: {e}", self.path.display()))?;
+
+        let mut versions = Vec::new();
+        for entry in entries {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().to_string();
+            if entry.path().is_dir() && semver::Version::parse(&name).is_ok() {
+                versions.push(name);
+            } else {
+                debug!("Skipping {:?} which is not a version directory", entry.path());
+            }
+        }
+
+        Ok(versions)
+    }
+
+    fn fetch_package_manifest(&self, version: &str) -> Result<Value, Error> {
+        let manifest_path = self.path.join(version).join("manifest.toml");
+        let manifest_contents = std::fs::read_to_string(&manifest_path)
+            .map_err(|e| anyhow::anyhow!("Could not read {}: {e}", manifest_path.display()))?;
+        toml::from_str(manifest_contents.as_str()).map_err(|e| anyhow::anyhow!("Failed to parse manifest: {e}"))
+    }
+}
+
+/// Returns the token configured for the server which the given url belongs to
+pub fn find_artifactory_token<'a>(artifactory_tokens: &'a HashMap<String, String>, url: &str) -> Option<&'a str> {
+    for (u, t) in artifactory_tokens {
+        if url.starts_with(u) {
+            return Some(t)
+        }
+    }
+
+    None
+}
+
+/// Order in which the solver tries the candidate versions of a package
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ResolutionMode {
+    /// Prefer the highest versions
+    #[default]
+    Highest,
+    /// Prefer the lowest versions, e.g. to check the lower bounds of requirements
+    Lowest,
+    /// Prefer the lowest versions for the direct dependencies of the root manifest and the
+    /// highest versions for all other packages
+    LowestDirect,
+}
+
 pub struct Inventory<'a> {
-    index: &'a Index,
+    registries: &'a Registries,
+    /// Registries selected for packages which are available from more than one registry
+    selected_registries: HashMap<String, String>,
     pool: Rc<Pool<VersionReq>>,
-    index_cache: IndexMap<String, IndexMap<Version, Package>>,
+    /// Package metadata per registry and index entry
+    index_cache: IndexMap<String, IndexMap<String, IndexMap<Version, Package>>>,
     cache_file: PathBuf,
-    git_cache_path: PathBuf,
+    package_cache: &'a Cache,
     artifactory_tokens: &'a HashMap<String, String>,
+    offline: bool,
+    /// Version constraints of the root manifest which apply without requiring the package
+    constraints: HashMap<String, semver::VersionReq>,
+    /// Packages which replace all versions from the index, e.g. path dependencies
+    overrides: HashMap<String, Package>,
+    /// Packages whose versions are taken from another index entry, e.g. a fork
+    patches: HashMap<String, String>,
+    /// Packages which are locked to a version if it is available
+    pins: HashMap<String, semver::Version>,
+    /// Versions from an existing lockfile which may be selected even if they are yanked
+    locked_versions: HashMap<String, semver::Version>,
+    /// Versions which are selected as long as they satisfy all requirements, e.g. from a lockfile
+    favored_versions: HashMap<String, semver::Version>,
+    resolution_mode: ResolutionMode,
+    /// Direct dependencies of the root manifest, used for `ResolutionMode::LowestDirect`
+    direct_dependencies: HashSet<String>,
+    /// Pre-release versions may be selected for all packages
+    prereleases: bool,
+    /// Packages for which pre-release versions may be selected
+    prerelease_packages: HashSet<String>,
+    /// Packages requested by the solver which are defined in more than one registry
+    ambiguous_packages: RefCell<BTreeMap<String, String>>,
+    /// Package versions tried by the solver whose dependencies cannot be resolved from the index
+    invalid_packages: RefCell<BTreeMap<String, String>>,
 }
 
 impl<'a> Inventory<'a> {
-    pub fn new(index: &'a Index, inventory_path: &Path, artifactory_tokens: &'a HashMap<String, String>) -> Result<Self, Error> {
-        let git_cache_path: PathBuf = inventory_path.join("git");
-        std::fs::create_dir_all(&git_cache_path)?;
-        debug!("Storing git caches in {:?}", git_cache_path);
+    pub fn new(registries: &'a Registries, inventory_path: &Path, package_cache: &'a Cache, artifactory_tokens: &'a HashMap<String, String>) -> Result<Self, Error> {
+        std::fs::create_dir_all(inventory_path)?;
 
         Ok(Inventory {
-            index,
+            registries,
+            selected_registries: HashMap::new(),
             pool: Rc::new(Pool::new()),
             index_cache: IndexMap::new(),
             cache_file: inventory_path.join("cache.json"),
-            git_cache_path,
-            artifactory_tokens
+            package_cache,
+            artifactory_tokens,
+            offline: false,
+            constraints: HashMap::new(),
+            overrides: HashMap::new(),
+            patches: HashMap::new(),
+            pins: HashMap::new(),
+            locked_versions: HashMap::new(),
+            favored_versions: HashMap::new(),
+            resolution_mode: ResolutionMode::default(),
+            direct_dependencies: HashSet::new(),
+            prereleases: false,
+            prerelease_packages: HashSet::new(),
+            ambiguous_packages: RefCell::new(BTreeMap::new()),
+            invalid_packages: RefCell::new(BTreeMap::new()),
         })
     }
 
-    fn make_metadata_retriever(&self, name: &str, index_entry: &Entry) -> Result<Box<dyn MetadataRetriever>, Error> {
+    /// In offline mode the inventory is only updated from local data, i.e. the cached bare
+    /// repositories and the existing cache file.
+    pub fn set_offline(&mut self, offline: bool) {
+        self.offline = offline;
+    }
+
+    /// Takes `package` from the given registry, even if other registries have an entry for it
+    pub fn select_registry(&mut self, package: &str, registry: &str) -> Result<(), Error> {
+        let index = self
+            .registries
+            .get(registry)
+            .ok_or(anyhow::anyhow!("Unknown registry '{registry}' for dependency '{package}'"))?;
+        index
+            .get_entry(package)
+            .map_err(|_| anyhow::anyhow!("No entry for '{package}' found in registry '{registry}'"))?;
+        self.selected_registries.insert(package.to_string(), registry.to_string());
+        Ok(())
+    }
+
+    /// Returns the name of the registry which provides `package`
+    fn find_registry(&self, package: &str) -> Result<&'a str, Error> {
+        let registries = self.registries;
+        if let Some(registry) = self.selected_registries.get(package) {
+            return registries
+                .names()
+                .into_iter()
+                .find(|name| name == registry)
+                .ok_or(anyhow::anyhow!("Unknown registry '{registry}'"));
+        }
+        match registries.find(package).as_slice() {
+            [] => Err(anyhow::anyhow!("No index entry found for '{package}'")),
+            [registry] => Ok(registry),
+            names => Err(anyhow::anyhow!(
+                "Package '{package}' is defined in the registries {}. Add `registry = \"<name>\"` to its dependency in the manifest to select one.",
+                names.iter().map(|n| format!("'{n}'")).collect::<Vec<_>>().join(", ")
+            )),
+        }
+    }
+
+    /// Returns the index which provides `package`
+    fn find_index(&self, package: &str) -> Result<&'a Index, Error> {
+        let registry = self.find_registry(package)?;
+        self.registries.get(registry).ok_or(anyhow::anyhow!("Unknown registry '{registry}'"))
+    }
+
+    /// Returns the cached versions of the index entry which provides `package`
+    fn cached_versions(&self, package: &str) -> Option<&IndexMap<Version, Package>> {
+        let entry_name = self.entry_name(package);
+        let registry = self.find_registry(entry_name).ok()?;
+        self.index_cache.get(registry)?.get(entry_name)
+    }
+
+    /// Returns the errors for packages which the solver could not take from a single registry
+    pub fn ambiguous_packages(&self) -> Vec<String> {
+        self.ambiguous_packages.borrow().values().cloned().collect()
+    }
+
+    /// Returns the errors for package versions from the index which the solver tried, but whose
+    /// dependencies use a source which is only supported in the root manifest
+    pub fn invalid_packages(&self) -> Vec<String> {
+        self.invalid_packages.borrow().values().cloned().collect()
+    }
+
+    /// Returns the name of the index entry which provides the versions of `package`
+    fn entry_name<'b>(&'b self, package: &'b str) -> &'b str {
+        self.patches.get(package).map_or(package, String::as_str)
+    }
+
+    /// Returns the index entry of `package` from the registry which provides it
+    pub fn get_entry(&self, package: &str) -> Result<&'a Entry, Error> {
+        let entry_name = self.entry_name(package);
+        self.find_index(entry_name)?.get_entry(entry_name)
+    }
+
+    pub fn get_version_metadata(&self, package: &str, version: &semver::Version) -> Option<&'a VersionMetadata> {
+        let entry_name = self.entry_name(package);
+        self.find_index(entry_name).ok()?.get_version_metadata(entry_name, version)
+    }
+
+    /// Makes `package` the only candidate for its name, regardless of the versions in the index
+    pub fn override_package(&mut self, package: Package) {
+        self.overrides.insert(package.name.clone(), package);
+    }
+
+    /// Takes all versions of `package` from the index entry `replacement` instead of its own
+    pub fn patch_package(&mut self, package: &str, replacement: &str) -> Result<(), Error> {
+        self.find_index(replacement)?;
+        self.patches.insert(package.to_string(), replacement.to_string());
+        Ok(())
+    }
+
+    /// Locks `name` to `version` so that only this version can be selected
+    pub fn pin_package(&mut self, name: &str, version: &semver::Version) {
+        self.pins.insert(name.to_string(), version.clone());
+    }
+
+    /// Allows `version` of `name` to be selected even if it has been yanked from the index
+    pub fn allow_locked(&mut self, name: &str, version: &semver::Version) {
+        self.locked_versions.insert(name.to_string(), version.clone());
+    }
+
+    /// Tries `version` of `name` before all other versions, it is only replaced if it does not
+    /// satisfy the requirements anymore
+    pub fn favor_package(&mut self, name: &str, version: &semver::Version) {
+        self.favored_versions.insert(name.to_string(), version.clone());
+    }
+
+    /// Sets the order in which versions are tried, `direct_dependencies` are the packages
+    /// required by the root manifest
+    pub fn set_resolution_mode(&mut self, mode: ResolutionMode, direct_dependencies: HashSet<String>) {
+        self.resolution_mode = mode;
+        self.direct_dependencies = direct_dependencies;
+    }
+
+    /// Allows pre-release versions of all packages to be selected if a requirement matches
+    /// their release version
+    pub fn set_prereleases(&mut self, prereleases: bool) {
+        self.prereleases = prereleases;
+    }
+
+    /// Allows pre-release versions of `name` to be selected for every requirement on it
+    pub fn allow_prereleases(&mut self, name: &str) {
+        self.prerelease_packages.insert(name.to_string());
+    }
+
+    pub fn allows_prereleases(&self, name: &str) -> bool {
+        self.prereleases || self.prerelease_packages.contains(name)
+    }
+
+    /// Restricts the versions of `name` which may be selected if another package requires it
+    pub fn add_constraint(&mut self, name: &str, req: &semver::VersionReq) {
+        self.constraints.insert(name.to_string(), req.clone());
+    }
+
+    fn make_metadata_retriever(&self, index_entry: &Entry) -> Result<Option<Box<dyn MetadataRetriever>>, Error> {
         match index_entry {
+            Entry::Git { url } if self.offline => {
+                match GitMetadataRetriever::open_cached(url, self.package_cache) {
+                    Ok(retriever) => {
+                        debug!("Using cached Git metadata retriever");
+                        Ok(Some(Box::new(retriever)))
+                    },
+                    Err(e) => {
+                        debug!("No cached Git metadata available: {e}");
+                        Ok(None)
+                    }
+                }
+            },
             Entry::Git { url } => {
                 debug!("Using Git metadata retriever");
-                Ok(Box::new(GitMetadataRetriever::new(name, url, self.git_cache_path.as_path())?))
+                Ok(Some(Box::new(GitMetadataRetriever::new(url, self.package_cache)?)))
             },
+            Entry::Artifactory { .. } | Entry::Http { .. } if self.offline => Ok(None),
             Entry::Artifactory { server, repo, path } => {
                 debug!("Using Artifactory metadata retriever");
                 let token = self.find_artifactory_token(server);
-                Ok(Box::new(ArtifactoryMetadataRetriever::new(server, repo, path, token)))
+                Ok(Some(Box::new(ArtifactoryMetadataRetriever::new(server, repo, path, token))))
+            },
+            Entry::Http { url, listing } => {
+                debug!("Using HTTP metadata retriever");
+                let token = self.find_artifactory_token(url);
+                Ok(Some(Box::new(HttpMetadataRetriever::new(url, *listing, token))))
+            },
+            Entry::Local { path } => {
+                debug!("Using local metadata retriever");
+                Ok(Some(Box::new(LocalMetadataRetriever::new(&local_path(path)))))
             }
         }
     }
 
-    pub fn find_artifactory_token(&self, url: &str) -> Option<&str> {
-        for (u, t) in self.artifactory_tokens {
-            if url.starts_with(u) {
-                return Some(t)
+    /// Returns the packages needed for the given requirements for which no package metadata is
+    /// available. The dependencies of all versions matching a requirement are followed.
+    pub fn uncached_packages(&self, requirements: &HashMap<String, semver::VersionReq>) -> Vec<String> {
+        let mut pending: Vec<_> = requirements.iter().map(|(name, req)| (name.clone(), req.clone(), false)).collect();
+        let mut visited = HashSet::new();
+        let mut uncached = Vec::new();
+        while let Some((name, req, pre)) = pending.pop() {
+            if !visited.insert(name.clone()) {
+                continue;
+            }
+            let versions = self.get_versions(&name);
+            if versions.is_empty() {
+                if self.get_entry(&name).is_ok() {
+                    uncached.push(name);
+                }
+                continue;
+            }
+
+            let req = VersionReq::new(req, pre || self.allows_prereleases(&name));
+            for version in versions {
+                if !req.matches(&Version(version.clone())) {
+                    continue;
+                }
+                let Ok(package) = self.get_package(&name, version) else {
+                    continue;
+                };
+                for (dep_name, dep) in package.dependencies.iter().filter(|(_, dep)| !dep.optional) {
+                    pending.push((dep_name.clone(), dep.version.clone(), dep.pre));
+                }
             }
         }
+        uncached.sort();
+        uncached
+    }
 
-        None
+    pub fn find_artifactory_token(&self, url: &str) -> Option<&str> {
+        find_artifactory_token(self.artifactory_tokens, url)
     }
 
     pub fn pool(&self) -> Rc<Pool<VersionReq>> {
         self.pool.clone()
     }
 
-    pub fn index(&self) -> &Index { &self.index }
-
     pub fn get_package(&self, name: &str, version: &semver::Version) -> Result<&Package, Error> {
-        let package_entry = self.index_cache.get(name).expect("Dependency '{name}' not found");
-        let version_entry= package_entry.get(&Version { 0: version.clone() }).expect("Dependency '{name}/{version}' not found");
+        if let Some(package) = self.overrides.get(name).filter(|p| p.version.0 == *version) {
+            return Ok(package);
+        }
+        let package_entry = self
+            .cached_versions(name)
+            .ok_or(anyhow::anyhow!("Dependency '{name}' not found"))?;
+        let version_entry = package_entry
+            .get(&Version(version.clone()))
+            .ok_or(anyhow::anyhow!("Dependency '{name}/{version}' not found"))?;
         Ok(version_entry)
     }
 
+    /// Returns all known versions of a package in ascending order
+    pub fn get_versions(&self, name: &str) -> Vec<&semver::Version> {
+        if let Some(package) = self.overrides.get(name) {
+            return vec![&package.version.0];
+        }
+        let mut versions: Vec<_> = self
+            .cached_versions(name)
+            .map(|versions| versions.keys().map(|v| &v.0).collect())
+            .unwrap_or_default();
+        versions.sort();
+        versions
+    }
+
     pub fn update_cache(&mut self) -> Result<(), Error> {
         let cache_contents = std::fs::read_to_string(&self.cache_file).unwrap_or_default();
         if cache_contents.len() > 0 {
             debug!("Reading existing cache from {:?}", &self.cache_file);
-            self.index_cache = serde_json::from_str(&cache_contents)?;
+            match serde_json::from_str(&cache_contents) {
+                Ok(index_cache) => self.index_cache = index_cache,
+                // Caches without registries are rebuilt
+                Err(e) => debug!("Ignoring existing cache in an outdated format: {e}"),
+            }
         }
 
-        for module in self.index.get_entries()? {
-            let index_entry = self.index.get_entry(&module)?;
-            let metadata_retriever = self.make_metadata_retriever(module, index_entry)?;
+        for module in self.registries.get_entries() {
+            // The own entry of a patched package is not used
+            if self.patches.contains_key(module) && !self.patches.values().any(|r| r == module) {
+                continue;
+            }
+            // Packages in several registries are only an error if they are requested
+            let registry = match self.find_registry(module) {
+                Ok(registry) => registry,
+                Err(e) => {
+                    debug!("Skipping {module}: {e}");
+                    continue;
+                }
+            };
+            let index_entry = self.find_index(module)?.get_entry(module)?;
+            let Some(metadata_retriever) = self.make_metadata_retriever(index_entry)? else {
+                debug!("Using cached metadata for {module}");
+                continue;
+            };
             let versions = metadata_retriever.fetch_versions()?;
             for ref version in versions {
-                let download_manifest = match self.index_cache.get(module) {
-                    Some(inner_map) => !inner_map.contains_key(&Version::from_str(&version)?),
+                // Tags like `latest` or `v1.0` are not versions of the package
+                let parsed_version = match Version::from_str(version) {
+                    Ok(parsed_version) => parsed_version,
+                    Err(e) => {
+                        debug!("Skipping version '{version}' of {module}, it is not a semver version: {e}");
+                        continue;
+                    }
+                };
+                let download_manifest = match self.index_cache.get(registry).and_then(|entries| entries.get(module)) {
+                    Some(inner_map) => !inner_map.contains_key(&parsed_version),
                     None => true,
                 };
                 if download_manifest {
@@ -234,19 +662,22 @@ impl<'a> Inventory<'a> {
                     let manifest = metadata_retriever.fetch_package_manifest(version)?;
                     let module_entry = self
                         .index_cache
+                        .entry(registry.to_string())
+                        .or_default()
                         .entry(module.to_string())
                         .or_insert_with(IndexMap::new);
                     let version_entry = module_entry
-                        .entry(Version::from_str(&version)?)
+                        .entry(parsed_version)
                         .or_insert_with(|| Package::new(module, version));
-                    if let Some(dependencies) = manifest.get("dependencies") {
-                        for (dep_name, dep_version) in dependencies.as_table().unwrap() {
-                            version_entry.add_dependency(
-                                dep_name,
-                                dep_version.as_str().unwrap()
-                            );
-                        }
-                    }
+                    let Value::Table(manifest) = manifest else {
+                        return Err(anyhow::anyhow!("Manifest of {module}/{version} is not a table"));
+                    };
+                    let dependencies = parse_dependencies(&manifest, "dependencies")
+                        .map_err(|e| anyhow::anyhow!("Manifest of {module}/{version}: {e}"))?;
+                    let constraints = parse_constraints(&manifest)
+                        .map_err(|e| anyhow::anyhow!("Manifest of {module}/{version}: {e}"))?;
+                    version_entry.set_dependencies(dependencies);
+                    version_entry.set_constraints(constraints);
                 }
             }
         }
@@ -256,16 +687,62 @@ impl<'a> Inventory<'a> {
     }
 
     pub fn add_package(&mut self, package: Package) {
+        let registry = self.find_registry(&package.name).unwrap_or(DEFAULT_REGISTRY);
         let entry = self
             .index_cache
+            .entry(registry.to_string())
+            .or_default()
             .entry(package.name.to_string())
             .or_insert_with(|| IndexMap::new());
         entry.insert(package.version.clone(), package);
     }
 
-    pub fn map_dependency(&self, name: &str, range: &VersionReq) -> VersionSetId {
+    /// Returns why a version of a package from the index may not be selected
+    fn exclusion_reason(&self, name: &str, version: &semver::Version) -> Option<String> {
+        let yanked = self.get_version_metadata(name, version).is_some_and(|metadata| metadata.yanked);
+        let allowed = self.pins.get(name) == Some(version) || self.locked_versions.get(name) == Some(version);
+        if yanked && !allowed {
+            return Some(format!("version {version} has been yanked"));
+        }
+        // Constraints follow the pre-release policy like requirements, e.g. `<2` allows `1.5.0-rc.1` with `--pre`
+        self.constraints
+            .get(name)
+            .filter(|req| !self.version_req(name, req, false).matches(&Version(version.clone())))
+            .map(|req| format!("excluded by the constraint '{req}' of the manifest"))
+    }
+
+    /// Returns all versions of a package in descending order, together with the reason why a
+    /// version may not be selected
+    pub fn candidate_versions(&self, name: &str) -> Vec<(semver::Version, Option<String>)> {
+        if let Some(package) = self.overrides.get(name) {
+            return vec![(package.version.0.clone(), None)];
+        }
+        let pin = self.pins.get(name);
+        let mut versions: Vec<_> = self
+            .get_versions(name)
+            .into_iter()
+            .map(|version| {
+                let reason = match pin {
+                    Some(pin) if pin != version => Some(format!("the package is kept at version {pin}")),
+                    _ => self.exclusion_reason(name, version),
+                };
+                (version.clone(), reason)
+            })
+            .collect();
+        versions.reverse();
+        versions
+    }
+
+    /// Returns the requirement on `name` as the solver sees it, `pre` allows pre-release versions
+    /// for this requirement in addition to the pre-release policy of the package
+    pub fn version_req(&self, name: &str, range: &semver::VersionReq, pre: bool) -> VersionReq {
+        VersionReq::new(range.clone(), pre || self.allows_prereleases(name))
+    }
+
+    /// Interns the requirement on `name`, see `version_req`
+    pub fn map_dependency(&self, name: &str, range: &semver::VersionReq, pre: bool) -> VersionSetId {
         let dep_name = self.pool.intern_package_name(name);
-        self.pool.intern_version_set(dep_name, range.clone())
+        self.pool.intern_version_set(dep_name, self.version_req(name, range, pre))
     }
 
     pub fn map_solvable(&self, solvable: &SolvableId) -> (&str, &semver::Version) {
@@ -284,16 +761,40 @@ impl DependencyProvider<VersionReq> for &Inventory<'_> {
         _solver: &SolverCache<VersionReq, String, Self>,
         solvables: &mut [SolvableId],
     ) {
+        let Some(first) = solvables.first() else {
+            return;
+        };
+        let name = self.pool.resolve_package_name(self.pool.resolve_solvable(*first).name_id());
+        let lowest = match self.resolution_mode {
+            ResolutionMode::Highest => false,
+            ResolutionMode::Lowest => true,
+            ResolutionMode::LowestDirect => self.direct_dependencies.contains(name),
+        };
         solvables.sort_by(|a, b| {
             let a = self.pool.resolve_solvable(*a).inner();
             let b = self.pool.resolve_solvable(*b).inner();
-            b.cmp(&a)
+            if lowest { a.cmp(b) } else { b.cmp(a) }
         });
     }
 
     async fn get_candidates(&self, name: NameId) -> Option<Candidates> {
         let package_name = self.pool.resolve_package_name(name);
-        let Some(package) = self.index_cache.get(package_name) else {
+        if let Some(package) = self.overrides.get(package_name) {
+            let solvable = self.pool.intern_solvable(name, package.version.clone());
+            return Some(Candidates {
+                candidates: vec![solvable],
+                locked: Some(solvable),
+                ..Candidates::default()
+            });
+        }
+
+        let Some(package) = self.cached_versions(package_name) else {
+            let entry_name = self.entry_name(package_name);
+            if self.registries.find(entry_name).len() > 1 {
+                if let Err(e) = self.find_registry(entry_name) {
+                    self.ambiguous_packages.borrow_mut().insert(package_name.to_string(), e.to_string());
+                }
+            }
             return None;
         };
 
@@ -301,9 +802,20 @@ impl DependencyProvider<VersionReq> for &Inventory<'_> {
             candidates: Vec::with_capacity(package.len()),
             ..Candidates::default()
         };
+        let pin = self.pins.get(package_name);
+        let favored = self.favored_versions.get(package_name);
         for version in package.keys() {
             let solvable = self.pool.intern_solvable(name, version.clone());
             candidates.candidates.push(solvable);
+            if pin == Some(&version.0) {
+                candidates.locked = Some(solvable);
+            }
+            if favored == Some(&version.0) {
+                candidates.favored = Some(solvable);
+            }
+            if let Some(reason) = self.exclusion_reason(package_name, &version.0) {
+                candidates.excluded.push((solvable, self.pool.intern_string(reason)));
+            }
         }
 
         Some(candidates)
@@ -313,24 +825,40 @@ impl DependencyProvider<VersionReq> for &Inventory<'_> {
         let candidate = self.pool.resolve_solvable(solvable);
         let package_name = self.pool.resolve_package_name(candidate.name_id());
         let version = candidate.inner();
-        let Some(deps) = self
-            .index_cache
-            .get(package_name)
-            .and_then(|v| v.get(version))
-            .and_then(|p| Some(&p.dependencies))
-        else {
-            return Dependencies::Known(Default::default());
+        let package = match self.overrides.get(package_name) {
+            Some(package) => package,
+            None => {
+                let Some(package) = self.cached_versions(package_name).and_then(|v| v.get(version)) else {
+                    return Dependencies::Known(Default::default());
+                };
+                // The keys refer to the registries and directories of the package's own project
+                let source_key = package.dependencies.iter().find_map(|(name, d)| d.source_key().map(|key| (name, key)));
+                if let Some((dep_name, key)) = source_key {
+                    let reason = format!(
+                        "Package {package_name}/{version} cannot be used: its dependency '{dep_name}' sets '{key}', which is only supported in the root manifest"
+                    );
+                    self.invalid_packages.borrow_mut().insert(format!("{package_name}/{version}"), reason.clone());
+                    return Dependencies::Unknown(self.pool.intern_string(reason));
+                }
+                package
+            }
         };
 
         let mut result = KnownDependencies {
-            requirements: Vec::with_capacity(deps.len()),
-            constrains: vec![],
+            requirements: Vec::with_capacity(package.dependencies.len()),
+            constrains: Vec::with_capacity(package.constraints.len()),
         };
 
-        for req in deps.iter() {
-            let dep_name = self.pool.intern_package_name(req.0);
-            let dep_spec = self.pool.intern_version_set(dep_name, req.1.clone().into());
-            result.requirements.push(dep_spec);
+        for (name, dependency) in package.dependencies.iter() {
+            let dep_spec = self.map_dependency(name, &dependency.version, dependency.pre);
+            if dependency.optional {
+                result.constrains.push(dep_spec);
+            } else {
+                result.requirements.push(dep_spec);
+            }
+        }
+        for (name, req) in package.constraints.iter() {
+            result.constrains.push(self.map_dependency(name, req, false));
         }
 
         Dependencies::Known(result)
@@ -341,10 +869,123 @@ impl DependencyProvider<VersionReq> for &Inventory<'_> {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_parse_listing() {
+        let html = r#"<html><body><h1>Index of /core/</h1><pre>
+            <a href="../">../</a>
+            <a href="1.0.0/">1.0.0/</a>       01-Jan-2024 10:00       -
+            <a href="1.1.0/">1.1.0/</a>       01-Feb-2024 10:00       -
+            <a href='/core/2.0.0-rc.1/'>2.0.0-rc.1/</a>
+            <a href="latest/">latest/</a>
+            <a href="versions.json">versions.json</a>
+        </pre></body></html>"#;
+        let retriever = HttpMetadataRetriever::new("https://example.com/core", Listing::Html, None);
+        assert_eq!(retriever.parse_listing(html), vec!["1.0.0", "1.1.0", "2.0.0-rc.1"]);
+
+        let html = r#"<a href="v1.0.0/">v1.0.0/</a> <a href="1.1.0/">1.1.0/</a>"#;
+        let retriever = HttpMetadataRetriever::new("https://example.com/core/v{version}/dist", Listing::Html, None);
+        assert_eq!(retriever.parse_listing(html), vec!["1.0.0"]);
+    }
+
+    /// Serves the given files over HTTP on a local port and returns the base URL
+    fn serve(files: HashMap<&'static str, &'static str>) -> String {
+        use std::io::{BufRead, BufReader, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut request_line = String::new();
+                BufReader::new(&stream).read_line(&mut request_line).unwrap();
+                let path = request_line.split_whitespace().nth(1).unwrap_or_default();
+                let response = match files.get(path) {
+                    Some(content) => format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{content}", content.len()),
+                    None => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string(),
+                };
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+        format!("http://{address}")
+    }
+
+    #[test]
+    fn test_http_retriever() -> Result<(), Error> {
+        let base_url = serve(HashMap::from([
+            ("/core/versions.json", r#"["1.0.0", "1.1.0"]"#),
+            ("/core/", r#"<a href="../">../</a><a href="1.0.0/">1.0.0/</a>"#),
+            ("/core/1.0.0/manifest.toml", "[package]\nname = \"core\"\n"),
+            ("/core/1.1.0/manifest.toml", "[dependencies]\nutils = \"^2\"\n"),
+        ]));
+
+        let retriever = HttpMetadataRetriever::new(&format!("{base_url}/core"), Listing::Json, None);
+        assert_eq!(retriever.fetch_versions()?, vec!["1.0.0", "1.1.0"]);
+        let manifest = retriever.fetch_package_manifest("1.1.0")?;
+        assert_eq!(manifest["dependencies"]["utils"].as_str(), Some("^2"));
+        assert!(retriever.fetch_package_manifest("2.0.0").is_err());
+
+        let retriever = HttpMetadataRetriever::new(&format!("{base_url}/core/{{version}}"), Listing::Html, None);
+        assert_eq!(retriever.fetch_versions()?, vec!["1.0.0"]);
+        Ok(())
+    }
+
+    /// Creates a git repository with one commit on `main` and the given tags
+    fn git_repository(path: &Path, files: &[(&str, &str)], tags: &[&str]) -> Result<(), Error> {
+        let repo = git2::Repository::init_opts(path, git2::RepositoryInitOptions::new().initial_head("main"))?;
+        let mut repo_index = repo.index()?;
+        for (name, content) in files {
+            std::fs::write(path.join(name), content)?;
+            repo_index.add_path(Path::new(name))?;
+        }
+        let tree = repo.find_tree(repo_index.write_tree()?)?;
+        let signature = git2::Signature::now("metabuild", "metabuild@example.com")?;
+        let commit = repo.commit(Some("HEAD"), &signature, &signature, "Initial commit", &tree, &[])?;
+        for tag in tags {
+            repo.tag_lightweight(tag, &repo.find_object(commit, None)?, false)?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_prereleases() -> Result<(), Error> {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let core_path = temp_dir.path().join("core");
+        git_repository(&core_path, &[("manifest.toml", "[dependencies]\n")], &["1.0.0", "2.0.0-rc.1", "latest"])?;
+        let entries = HashMap::from([("core", Entry::Git { url: core_path.to_string_lossy().to_string() })]);
+        let index_path = temp_dir.path().join("index-source");
+        git_repository(&index_path, &[("index.json", &serde_json::to_string(&entries)?)], &[])?;
+        let index = Index::new(&index_path.to_string_lossy(), "main", &temp_dir.path().join("index"))?;
+        let registries = Registries::from(index);
+        let cache = Cache::new(&temp_dir.path().join("cache"))?;
+        let tokens = HashMap::new();
+        let mut inventory = Inventory::new(&registries, temp_dir.path(), &cache, &tokens)?;
+
+        // The tag `latest` is skipped instead of failing the update
+        inventory.update_cache()?;
+        let versions: Vec<String> = inventory.get_versions("core").iter().map(|v| v.to_string()).collect();
+        assert_eq!(versions, vec!["1.0.0", "2.0.0-rc.1"]);
+
+        let dependencies = HashMap::from([("core".to_string(), semver::VersionReq::parse(">=1")?)]);
+        assert_eq!(crate::solve(&inventory, dependencies.clone())?["core"], semver::Version::parse("1.0.0")?);
+        let explicit = HashMap::from([("core".to_string(), semver::VersionReq::parse("=2.0.0-rc.1")?)]);
+        assert_eq!(crate::solve(&inventory, explicit)?["core"], semver::Version::parse("2.0.0-rc.1")?);
+
+        inventory.allow_prereleases("core");
+        assert_eq!(crate::solve(&inventory, dependencies.clone())?["core"], semver::Version::parse("2.0.0-rc.1")?);
+
+        inventory.add_constraint("core", &semver::VersionReq::parse("<3")?);
+        assert_eq!(crate::solve(&inventory, dependencies)?["core"], semver::Version::parse("2.0.0-rc.1")?);
+        Ok(())
+    }
+
     #[test]
     fn test_update_cache() -> Result<(), anyhow::Error> {
         let temp_dir = tempfile::tempdir().unwrap();
-        let mut inventory = Inventory::new("https://github.com/jasal82/index.git", temp_dir.path(), &Vec::new());
+        let index = Index::new("https://github.com/jasal82/index.git", "main", &temp_dir.path().join("index"))?;
+        let registries = Registries::from(index);
+        let cache = Cache::new(&temp_dir.path().join("cache"))?;
+        let tokens = HashMap::new();
+        let mut inventory = Inventory::new(&registries, temp_dir.path(), &cache, &tokens)?;
         inventory.update_cache()
     }
 }
@@ -1,24 +1,32 @@
+pub mod cache;
+pub mod conflict;
 pub mod index;
 pub mod inventory;
+pub mod manifest;
 pub mod package;
-mod repository;
+pub mod repository;
 
+use conflict::ConflictReport;
 use inventory::Inventory;
 use itertools::Itertools;
-use package::VersionReq;
 use resolvo::{DefaultSolvableDisplay, Solver, UnsolvableOrCancelled};
 use std::collections::HashMap;
 
 #[derive(Debug)]
 pub enum SolverError {
-    Unsolvable(String),
+    Unsolvable(ConflictReport),
+    /// Requested packages which are provided by more than one registry
+    Ambiguous(Vec<String>),
+    /// Packages from the index whose dependencies use `registry`, `path` or `git`
+    InvalidDependencies(Vec<String>),
     Cancelled,
 }
 
 impl std::fmt::Display for SolverError {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
-            SolverError::Unsolvable(_) => write!(f, "Unsolvable"),
+            SolverError::Unsolvable(report) => write!(f, "Could not resolve dependencies:\n{report}"),
+            SolverError::Ambiguous(errors) | SolverError::InvalidDependencies(errors) => write!(f, "{}", errors.join("\n")),
             SolverError::Cancelled => write!(f, "Operation cancelled"),
         }
     }
@@ -28,6 +36,8 @@ impl std::error::Error for SolverError {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         match self {
             SolverError::Unsolvable(_) => None,
+            SolverError::Ambiguous(_) => None,
+            SolverError::InvalidDependencies(_) => None,
             SolverError::Cancelled => None,
         }
     }
@@ -37,9 +47,37 @@ pub fn solve(
     inventory: &Inventory,
     dependencies: HashMap<String, semver::VersionReq>,
 ) -> Result<HashMap<String, semver::Version>, SolverError> {
+    let result = run_solver(inventory, &dependencies);
+    // Such dependencies must not be resolved by name, even if the solver found other versions
+    let invalid = inventory.invalid_packages();
+    if !invalid.is_empty() {
+        return Err(SolverError::InvalidDependencies(invalid));
+    }
+    match result {
+        Ok(result) => Ok(result),
+        Err(Some(details)) => {
+            let ambiguous = inventory.ambiguous_packages();
+            if !ambiguous.is_empty() {
+                return Err(SolverError::Ambiguous(ambiguous));
+            }
+            let report = conflict::analyze(inventory, &dependencies, details, |dependencies| {
+                run_solver(inventory, dependencies).is_ok()
+            });
+            Err(SolverError::Unsolvable(report))
+        }
+        Err(None) => Err(SolverError::Cancelled),
+    }
+}
+
+/// Runs the solver, the error contains the explanation of the solver if the dependencies are
+/// unsolvable and is empty if solving was cancelled
+fn run_solver(
+    inventory: &Inventory,
+    dependencies: &HashMap<String, semver::VersionReq>,
+) -> Result<HashMap<String, semver::Version>, Option<String>> {
     let mapped_dependencies = dependencies
         .iter()
-        .map(|d| inventory.map_dependency(d.0, &VersionReq { 0: d.1.clone() }))
+        .map(|d| inventory.map_dependency(d.0, d.1, false))
         .collect_vec();
     let mut solver = Solver::new(inventory);
     match solver.solve(mapped_dependencies) {
@@ -52,9 +90,9 @@ pub fn solve(
             let reason = problem
                 .display_user_friendly(&solver, inventory.pool(), &DefaultSolvableDisplay)
                 .to_string();
-            Err(SolverError::Unsolvable(reason))
+            Err(Some(reason))
         }
-        Err(UnsolvableOrCancelled::Cancelled(_)) => Err(SolverError::Cancelled),
+        Err(UnsolvableOrCancelled::Cancelled(_)) => Err(None),
     }
 }
 
@@ -62,12 +100,64 @@ pub fn solve(
 mod tests {
     use super::*;
     use anyhow::Error;
+    use cache::Cache;
+    use index::{Entry, Index, Registries};
+    use indexmap::IndexMap;
+    use inventory::ResolutionMode;
+    use package::Package;
+    use std::collections::HashSet;
+    use std::path::Path;
+
+    /// Creates an index repository with a local entry for every package in the fixtures
+    fn fixture_index(temp_dir: &Path) -> Result<Index, Error> {
+        let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/packages");
+        let mut entries = IndexMap::new();
+        for entry in std::fs::read_dir(&fixtures)? {
+            let entry = entry?;
+            let path = entry.path().to_string_lossy().to_string();
+            entries.insert(entry.file_name().to_string_lossy().to_string(), Entry::Local { path });
+        }
+
+        let source_path = temp_dir.join("index-source");
+        let repo = git2::Repository::init_opts(&source_path, git2::RepositoryInitOptions::new().initial_head("main"))?;
+        std::fs::write(source_path.join("index.json"), serde_json::to_string_pretty(&entries)?)?;
+        let mut repo_index = repo.index()?;
+        repo_index.add_path(Path::new("index.json"))?;
+        let tree = repo.find_tree(repo_index.write_tree()?)?;
+        let signature = git2::Signature::now("metabuild", "metabuild@example.com")?;
+        repo.commit(Some("HEAD"), &signature, &signature, "Add fixtures", &tree, &[])?;
+
+        Index::new(&source_path.to_string_lossy(), "main", &temp_dir.join("index"))
+    }
+
+    /// Registries and package cache which an inventory of the fixture packages borrows
+    struct Fixture {
+        temp_dir: tempfile::TempDir,
+        registries: Registries,
+        cache: Cache,
+        tokens: HashMap<String, String>,
+    }
+
+    impl Fixture {
+        fn new() -> Result<Self, Error> {
+            let temp_dir = tempfile::tempdir()?;
+            let registries = Registries::from(fixture_index(temp_dir.path())?);
+            let cache = Cache::new(&temp_dir.path().join("cache"))?;
+            Ok(Self { temp_dir, registries, cache, tokens: HashMap::new() })
+        }
+    }
+
+    /// Creates an inventory of the fixture packages with an updated cache
+    fn fixture_inventory(fixture: &Fixture) -> Result<Inventory<'_>, Error> {
+        let mut inventory = Inventory::new(&fixture.registries, fixture.temp_dir.path(), &fixture.cache, &fixture.tokens)?;
+        inventory.update_cache()?;
+        Ok(inventory)
+    }
 
     #[test]
     fn test_solvable() -> Result<(), Error> {
-        let temp_dir = tempfile::tempdir().unwrap();
-        let mut inventory = Inventory::new("https://github.com/jasal82/index.git", temp_dir.path(), &HashMap::new());
-        inventory.update_cache()?;
+        let fixture = Fixture::new()?;
+        let inventory = fixture_inventory(&fixture)?;
         let mut dependencies: HashMap<String, semver::VersionReq> = HashMap::new();
         dependencies.insert("module1".to_string(), semver::VersionReq::parse("^1")?);
         dependencies.insert("module2".to_string(), semver::VersionReq::parse("^2")?);
@@ -89,11 +179,82 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_package_constraints() -> Result<(), Error> {
+        let fixture = Fixture::new()?;
+        let mut inventory = fixture_inventory(&fixture)?;
+        let mut app = package::Package::new("app", "1.0.0");
+        app.add_dependency("module1", "^1");
+        app.set_constraints(IndexMap::from([
+            ("module2".to_string(), semver::VersionReq::parse("<2.4.1")?),
+            ("unused".to_string(), semver::VersionReq::parse("^1")?),
+        ]));
+        inventory.override_package(app);
+        let mut dependencies: HashMap<String, semver::VersionReq> = HashMap::new();
+        dependencies.insert("app".to_string(), semver::VersionReq::STAR);
+        dependencies.insert("module2".to_string(), semver::VersionReq::STAR);
+        let result = solve(&inventory, dependencies.clone())?;
+        assert_eq!(result["module2"], semver::Version::parse("2.4.0")?);
+        assert!(!result.contains_key("unused"));
+
+        dependencies.insert("module2".to_string(), semver::VersionReq::parse("^3")?);
+        let Err(SolverError::Unsolvable(report)) = solve(&inventory, dependencies) else {
+            panic!("Expected the constraint to conflict with the requirement");
+        };
+        let constraint = report.conflicts[0].chains.iter().find(|c| c.optional).unwrap();
+        assert_eq!(constraint.path[0].package, "app");
+        assert_eq!(constraint.requirement.to_string(), "<2.4.1");
+        Ok(())
+    }
+
+    #[test]
+    fn test_patch_package() -> Result<(), Error> {
+        let fixture = Fixture::new()?;
+        let mut inventory = fixture_inventory(&fixture)?;
+        assert!(inventory.patch_package("module1", "unknown").is_err());
+        inventory.patch_package("module1", "module2")?;
+        let mut dependencies: HashMap<String, semver::VersionReq> = HashMap::new();
+        dependencies.insert("module1".to_string(), semver::VersionReq::parse("~2.3")?);
+        let result = solve(&inventory, dependencies)?;
+        assert_eq!(result.len(), 1);
+        assert_eq!(result["module1"], semver::Version::parse("2.3.0")?);
+        let Entry::Local { path } = inventory.get_entry("module1")? else {
+            panic!("Expected the local entry of module2");
+        };
+        assert!(path.ends_with("module2"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_resolution_mode() -> Result<(), Error> {
+        let fixture = Fixture::new()?;
+        let mut inventory = fixture_inventory(&fixture)?;
+        let mut dependencies: HashMap<String, semver::VersionReq> = HashMap::new();
+        dependencies.insert("module1".to_string(), semver::VersionReq::parse("^1")?);
+        dependencies.insert("module2".to_string(), semver::VersionReq::parse(">=2.3")?);
+        let direct: HashSet<String> = ["module2".to_string()].into();
+
+        for (mode, module1, module2) in [
+            (ResolutionMode::Highest, "1.0.1", "2.4.0"),
+            (ResolutionMode::Lowest, "1.0.0", "2.4.0"),
+            (ResolutionMode::LowestDirect, "1.0.1", "2.4.0"),
+        ] {
+            inventory.set_resolution_mode(mode, direct.clone());
+            let result = solve(&inventory, dependencies.clone())?;
+            assert_eq!(result["module1"], semver::Version::parse(module1)?, "{mode:?}");
+            assert_eq!(result["module2"], semver::Version::parse(module2)?, "{mode:?}");
+        }
+
+        dependencies.remove("module1");
+        inventory.set_resolution_mode(ResolutionMode::LowestDirect, direct);
+        assert_eq!(solve(&inventory, dependencies)?["module2"], semver::Version::parse("2.3.0")?);
+        Ok(())
+    }
+
     #[test]
     fn test_unsolvable() -> Result<(), Error> {
-        let temp_dir = tempfile::tempdir().unwrap();
-        let mut inventory = Inventory::new("https://github.com/jasal82/index.git", temp_dir.path(), &HashMap::new());
-        inventory.update_cache()?;
+        let fixture = Fixture::new()?;
+        let inventory = fixture_inventory(&fixture)?;
         let mut dependencies: HashMap<String, semver::VersionReq> = HashMap::new();
         dependencies.insert("module1".to_string(), semver::VersionReq::parse("^1")?);
         dependencies.insert("module2".to_string(), semver::VersionReq::parse("~2.3.0")?);
@@ -107,4 +268,104 @@ mod tests {
         }
         Ok(())
     }
+
+    #[test]
+    fn test_conflict_report() -> Result<(), Error> {
+        let fixture = Fixture::new()?;
+        let inventory = fixture_inventory(&fixture)?;
+        let mut dependencies: HashMap<String, semver::VersionReq> = HashMap::new();
+        dependencies.insert("module1".to_string(), semver::VersionReq::parse("^1")?);
+        dependencies.insert("module2".to_string(), semver::VersionReq::parse("~2.3.0")?);
+        let Err(SolverError::Unsolvable(report)) = solve(&inventory, dependencies) else {
+            panic!("Expected the dependencies to be unsolvable");
+        };
+
+        assert_eq!(report.requirements.keys().collect::<Vec<_>>(), vec!["module1", "module2"]);
+        assert_eq!(report.conflicts.len(), 1);
+        let conflict = &report.conflicts[0];
+        assert_eq!(conflict.package, "module2");
+        let sources: Vec<_> = conflict
+            .chains
+            .iter()
+            .map(|c| (c.path.iter().map(|n| format!("{} {}", n.package, n.version)).collect::<Vec<_>>(), c.requirement.to_string()))
+            .collect();
+        assert_eq!(sources, vec![
+            (vec!["module1 1.0.1".to_string()], "^2.4".to_string()),
+            (vec![], "~2.3.0".to_string()),
+        ]);
+        let rejected: Vec<_> = conflict.rejected.iter().map(|r| (r.version.to_string(), r.reason.as_str())).collect();
+        assert_eq!(rejected, vec![
+            ("3.0.0".to_string(), "does not match ^2.4 required by module1 1.0.1"),
+            ("2.4.0".to_string(), "does not match ~2.3.0 required by the manifest"),
+            ("2.3.0".to_string(), "does not match ^2.4 required by module1 1.0.1"),
+        ]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_conflict_report_prerelease() -> Result<(), Error> {
+        let fixture = Fixture::new()?;
+        let mut inventory = fixture_inventory(&fixture)?;
+        // A path package in development, it matches `*` like in the solver
+        let mut app = Package::new("app", "1.0.0-dev");
+        app.add_dependency("module2", "^3");
+        inventory.override_package(app);
+        inventory.allow_prereleases("app");
+        let mut dependencies: HashMap<String, semver::VersionReq> = HashMap::new();
+        dependencies.insert("app".to_string(), semver::VersionReq::STAR);
+        dependencies.insert("module1".to_string(), semver::VersionReq::parse("^1")?);
+        let Err(SolverError::Unsolvable(report)) = solve(&inventory, dependencies) else {
+            panic!("Expected the dependencies to be unsolvable");
+        };
+
+        let conflicts: Vec<_> = report.conflicts.iter().map(|c| c.package.as_str()).collect();
+        assert_eq!(conflicts, vec!["module2"]);
+        let sources: Vec<_> = report.conflicts[0]
+            .chains
+            .iter()
+            .map(|c| (c.path.iter().map(|n| format!("{} {}", n.package, n.version)).collect::<Vec<_>>(), c.requirement.to_string()))
+            .collect();
+        assert_eq!(sources, vec![
+            (vec!["app 1.0.0-dev".to_string()], "^3".to_string()),
+            (vec!["module1 1.0.1".to_string()], "^2.4".to_string()),
+        ]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_dependencies() -> Result<(), Error> {
+        let fixture = Fixture::new()?;
+        let inventory = fixture_inventory(&fixture)?;
+        let dependencies = HashMap::from([("module3".to_string(), semver::VersionReq::parse("^1")?)]);
+        let Err(SolverError::InvalidDependencies(errors)) = solve(&inventory, dependencies) else {
+            panic!("Expected the registry key of module3's dependency to be rejected");
+        };
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].contains("module3/1.0.0") && errors[0].contains("'module2' sets 'registry'"), "{}", errors[0]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_ambiguous_registries() -> Result<(), Error> {
+        let mut fixture = Fixture::new()?;
+        let mirror = fixture_index(&fixture.temp_dir.path().join("mirror"))?;
+        fixture.registries.add("mirror", mirror);
+        let mut dependencies: HashMap<String, semver::VersionReq> = HashMap::new();
+        dependencies.insert("module1".to_string(), semver::VersionReq::parse("^1")?);
+
+        // Both registries provide every package, which is only an error once a package is requested
+        let inventory = fixture_inventory(&fixture)?;
+        let Err(SolverError::Ambiguous(errors)) = solve(&inventory, dependencies.clone()) else {
+            panic!("Expected module1 to be ambiguous");
+        };
+        assert!(errors[0].contains("'module1'"));
+
+        let mut inventory = Inventory::new(&fixture.registries, fixture.temp_dir.path(), &fixture.cache, &fixture.tokens)?;
+        inventory.select_registry("module1", "mirror")?;
+        inventory.select_registry("module2", "mirror")?;
+        inventory.update_cache()?;
+        let result = solve(&inventory, dependencies)?;
+        assert_eq!(result["module1"], semver::Version::parse("1.0.1")?);
+        Ok(())
+    }
 }
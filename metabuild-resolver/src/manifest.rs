@@ -0,0 +1,446 @@
+use anyhow::{anyhow, Error};
+use indexmap::IndexMap;
+use serde::de::{self, MapAccess, Visitor};
+use serde::ser::SerializeMap;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::path::Path;
+
+use crate::cache::Cache;
+use crate::package::Package;
+use crate::repository::{BareRepository, RefType};
+
+/// Revision of a git dependency, the default branch of the repository if none is given
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GitReference {
+    Tag(String),
+    Branch(String),
+    Rev(String),
+    DefaultBranch,
+}
+
+impl GitReference {
+    pub fn ref_type(&self) -> RefType {
+        match self {
+            GitReference::Tag(tag) => RefType::Tag(tag.clone()),
+            GitReference::Branch(branch) => RefType::Branch(branch.clone()),
+            GitReference::Rev(rev) => RefType::Commit(rev.clone()),
+            GitReference::DefaultBranch => RefType::Commit("HEAD".to_string()),
+        }
+    }
+}
+
+impl fmt::Display for GitReference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GitReference::Tag(tag) => write!(f, "tag {tag}"),
+            GitReference::Branch(branch) => write!(f, "branch {branch}"),
+            GitReference::Rev(rev) => write!(f, "rev {rev}"),
+            GitReference::DefaultBranch => write!(f, "default branch"),
+        }
+    }
+}
+
+/// A package which is taken directly from a git repository instead of the index
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GitSource {
+    pub url: String,
+    pub reference: GitReference,
+}
+
+/// A dependency as declared in a manifest, either in the short form `foo = "^1.2"` or as a
+/// table `foo = { version = "^1.2", registry = "default", optional = true }`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dependency {
+    /// Version requirement, defaults to `*` for path dependencies
+    pub version: semver::VersionReq,
+    /// Name of the registry the dependency is resolved from, the default registry if not set
+    pub registry: Option<String>,
+    /// Directory of a local package which is used instead of the index, relative to the manifest
+    pub path: Option<String>,
+    /// Git repository which is used instead of the index
+    pub git: Option<GitSource>,
+    /// Optional dependencies are not installed on their own, they only restrict the version
+    /// if the package is required by another dependency
+    pub optional: bool,
+    /// Pre-release versions are selected if the requirement matches their release version
+    pub pre: bool,
+}
+
+impl Dependency {
+    pub fn new(version: semver::VersionReq) -> Self {
+        Self {
+            version,
+            registry: None,
+            path: None,
+            git: None,
+            optional: false,
+            pre: false,
+        }
+    }
+
+    /// Returns the key which selects another source than the index. Such sources are only
+    /// supported in the root manifest, not in published packages.
+    pub fn source_key(&self) -> Option<&'static str> {
+        if self.registry.is_some() {
+            Some("registry")
+        } else if self.path.is_some() {
+            Some("path")
+        } else if self.git.is_some() {
+            Some("git")
+        } else {
+            None
+        }
+    }
+
+    fn is_simple(&self) -> bool {
+        self.registry.is_none() && self.path.is_none() && self.git.is_none() && !self.optional && !self.pre
+    }
+}
+
+fn parse_version_req<E: de::Error>(value: &str) -> Result<semver::VersionReq, E> {
+    semver::VersionReq::parse(value).map_err(|e| E::custom(format!("invalid version requirement '{value}': {e}")))
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DetailedDependency {
+    version: Option<String>,
+    registry: Option<String>,
+    path: Option<String>,
+    git: Option<String>,
+    tag: Option<String>,
+    branch: Option<String>,
+    rev: Option<String>,
+    #[serde(default)]
+    optional: bool,
+    #[serde(default)]
+    pre: bool,
+}
+
+impl DetailedDependency {
+    fn git_source<E: de::Error>(&self) -> Result<Option<GitSource>, E> {
+        let reference = match (&self.tag, &self.branch, &self.rev) {
+            (None, None, None) => GitReference::DefaultBranch,
+            (Some(tag), None, None) => GitReference::Tag(tag.clone()),
+            (None, Some(branch), None) => GitReference::Branch(branch.clone()),
+            (None, None, Some(rev)) => GitReference::Rev(rev.clone()),
+            _ => return Err(E::custom("only one of 'tag', 'branch' and 'rev' can be specified")),
+        };
+
+        match &self.git {
+            Some(url) => Ok(Some(GitSource { url: url.clone(), reference })),
+            None if reference == GitReference::DefaultBranch => Ok(None),
+            None => Err(E::custom("'tag', 'branch' and 'rev' require 'git'")),
+        }
+    }
+}
+
+impl Serialize for Dependency {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        if self.is_simple() {
+            return self.version.to_string().serialize(serializer);
+        }
+
+        let mut map = serializer.serialize_map(None)?;
+        map.serialize_entry("version", &self.version.to_string())?;
+        if let Some(registry) = &self.registry {
+            map.serialize_entry("registry", registry)?;
+        }
+        if let Some(path) = &self.path {
+            map.serialize_entry("path", path)?;
+        }
+        if let Some(git) = &self.git {
+            map.serialize_entry("git", &git.url)?;
+            match &git.reference {
+                GitReference::Tag(tag) => map.serialize_entry("tag", tag)?,
+                GitReference::Branch(branch) => map.serialize_entry("branch", branch)?,
+                GitReference::Rev(rev) => map.serialize_entry("rev", rev)?,
+                GitReference::DefaultBranch => {}
+            }
+        }
+        if self.optional {
+            map.serialize_entry("optional", &true)?;
+        }
+        if self.pre {
+            map.serialize_entry("pre", &true)?;
+        }
+        map.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Dependency {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct DependencyVisitor;
+
+        impl<'de> Visitor<'de> for DependencyVisitor {
+            type Value = Dependency;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a version requirement string or a table with a 'version' key")
+            }
+
+            fn visit_str<E>(self, value: &str) -> Result<Self::Value, E>
+            where
+                E: de::Error,
+            {
+                parse_version_req(value).map(Dependency::new)
+            }
+
+            fn visit_map<M>(self, map: M) -> Result<Self::Value, M::Error>
+            where
+                M: MapAccess<'de>,
+            {
+                let detailed = DetailedDependency::deserialize(de::value::MapAccessDeserializer::new(map))?;
+                let git = detailed.git_source()?;
+                let sources = [detailed.registry.is_some(), detailed.path.is_some(), git.is_some()];
+                if sources.iter().filter(|s| **s).count() > 1 {
+                    return Err(de::Error::custom("only one of 'registry', 'path' and 'git' can be specified"));
+                }
+                let version = match (&detailed.version, detailed.path.is_some() || git.is_some()) {
+                    (Some(version), _) => parse_version_req(version)?,
+                    (None, true) => semver::VersionReq::STAR,
+                    (None, false) => {
+                        return Err(de::Error::custom("either 'version', 'path' or 'git' must be specified"))
+                    }
+                };
+                Ok(Dependency {
+                    version,
+                    registry: detailed.registry,
+                    path: detailed.path,
+                    git,
+                    optional: detailed.optional,
+                    pre: detailed.pre,
+                })
+            }
+        }
+
+        deserializer.deserialize_any(DependencyVisitor)
+    }
+}
+
+/// Replacement of a package for the whole dependency graph, as declared in `[patch]`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Patch {
+    /// Another index entry, e.g. a fork of the package
+    Entry {
+        package: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        registry: Option<String>,
+    },
+    /// Path or git source
+    Direct(Dependency),
+}
+
+impl fmt::Display for Patch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Patch::Direct(Dependency { path: Some(path), .. }) => write!(f, "path {path}"),
+            Patch::Direct(Dependency { git: Some(git), .. }) => write!(f, "git {} ({})", git.url, git.reference),
+            Patch::Direct(_) => write!(f, "unknown source"),
+            Patch::Entry { package, registry: None } => write!(f, "index entry '{package}'"),
+            Patch::Entry { package, registry: Some(registry) } => {
+                write!(f, "index entry '{package}' of registry '{registry}'")
+            },
+        }
+    }
+}
+
+/// Parses the dependency table `section` of a manifest. Errors name the offending key.
+pub fn parse_dependencies(manifest: &toml::Table, section: &str) -> Result<IndexMap<String, Dependency>, Error> {
+    let Some(table) = manifest.get(section) else {
+        return Ok(IndexMap::new());
+    };
+    let table = table.as_table().ok_or(anyhow!("[{section}] must be a table"))?;
+
+    table
+        .iter()
+        .map(|(name, value)| {
+            Dependency::deserialize(value.clone())
+                .map(|dependency| (name.clone(), dependency))
+                .map_err(|e| anyhow!("Invalid dependency '{name}' in [{section}]: {e}"))
+        })
+        .collect()
+}
+
+/// Parses the `[constraints]` table of a manifest, which maps package names to version
+/// requirements that apply whenever the package is part of the graph
+pub fn parse_constraints(manifest: &toml::Table) -> Result<IndexMap<String, semver::VersionReq>, Error> {
+    let Some(table) = manifest.get("constraints") else {
+        return Ok(IndexMap::new());
+    };
+    let table = table.as_table().ok_or(anyhow!("[constraints] must be a table"))?;
+
+    table
+        .iter()
+        .map(|(name, value)| {
+            let req = value
+                .as_str()
+                .ok_or(anyhow!("Invalid constraint '{name}' in [constraints]: expected a version requirement string"))?;
+            let req = parse_version_req::<de::value::Error>(req)
+                .map_err(|e| anyhow!("Invalid constraint '{name}' in [constraints]: {e}"))?;
+            Ok((name.clone(), req))
+        })
+        .collect()
+}
+
+/// Creates the package `name` from its manifest. The version is taken from `package.version`
+/// or from `default_version` if the manifest has none.
+fn package_from_manifest(name: &str, content: &str, default_version: Option<&str>) -> Result<Package, Error> {
+    let manifest: toml::Table = toml::from_str(content).map_err(|e| anyhow!("Could not parse manifest: {e}"))?;
+
+    let version = manifest
+        .get("package")
+        .and_then(|p| p.get("version"))
+        .and_then(toml::Value::as_str)
+        .or(default_version)
+        .ok_or(anyhow!("'package.version' is missing in the manifest"))?;
+    let version = semver::Version::parse(version).map_err(|e| anyhow!("Invalid version '{version}': {e}"))?;
+    let mut package = Package::new(name, &version.to_string());
+    package.set_dependencies(parse_dependencies(&manifest, "dependencies")?);
+    package.set_constraints(parse_constraints(&manifest)?);
+    Ok(package)
+}
+
+/// Reads the manifest of the local package `name` in `directory`. The version is taken from
+/// `package.version` since local packages have no tags.
+pub fn read_local_package(name: &str, directory: &Path) -> Result<Package, Error> {
+    let manifest_path = directory.join("manifest.toml");
+    let content = std::fs::read_to_string(&manifest_path)
+        .map_err(|e| anyhow!("Could not read manifest of path dependency '{name}' ({}): {e}", manifest_path.display()))?;
+    package_from_manifest(name, &content, None)
+        .map_err(|e| anyhow!("Path dependency '{name}' ({}): {e}", manifest_path.display()))
+}
+
+/// Reads the manifest of the git dependency `name` at the requested reference and returns the
+/// package together with the commit the reference points to. The version is taken from
+/// `package.version`, or from the tag if the manifest has none.
+pub fn read_git_package(name: &str, source: &GitSource, cache: &Cache, offline: bool) -> Result<(Package, String), Error> {
+    let storage_path = cache.git_path(&source.url);
+    let repo = if offline {
+        BareRepository::open(&source.url, &storage_path)?
+    } else {
+        let repo = BareRepository::new(&source.url, Some(storage_path.as_path()))?;
+        cache.mark_git_used(&source.url);
+        repo
+    };
+
+    let ref_type = source.reference.ref_type();
+    let commit = repo
+        .get_commit(&ref_type)
+        .map_err(|e| anyhow!("Git dependency '{name}' ({}, {}): {e}", source.url, source.reference))?;
+    let content = String::from_utf8(repo.get_file(&RefType::Commit(commit.clone()), Path::new("manifest.toml"))?)?;
+    let default_version = match &source.reference {
+        GitReference::Tag(tag) => Some(tag.as_str()),
+        _ => None,
+    };
+    let package = package_from_manifest(name, &content, default_version)
+        .map_err(|e| anyhow!("Git dependency '{name}' ({}, {}): {e}", source.url, source.reference))?;
+    Ok((package, commit))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_dependencies() -> Result<(), Error> {
+        let manifest: toml::Table = toml::from_str(
+            r#"
+            [dependencies]
+            core = "^1"
+            tasks = { version = "1.7.5", registry = "default", optional = true }
+            utils = { git = "https://host/utils.git", branch = "develop" }
+            "#,
+        )?;
+        let dependencies = parse_dependencies(&manifest, "dependencies")?;
+        assert_eq!(dependencies["core"], Dependency::new(semver::VersionReq::parse("^1")?));
+        assert_eq!(
+            dependencies["tasks"],
+            Dependency {
+                version: semver::VersionReq::parse("1.7.5")?,
+                registry: Some("default".to_string()),
+                path: None,
+                git: None,
+                optional: true,
+                pre: false,
+            }
+        );
+        assert_eq!(
+            dependencies["utils"].git,
+            Some(GitSource {
+                url: "https://host/utils.git".to_string(),
+                reference: GitReference::Branch("develop".to_string()),
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_local_package() -> Result<(), Error> {
+        let temp_dir = tempfile::tempdir()?;
+        std::fs::write(
+            temp_dir.path().join("manifest.toml"),
+            "[package]\nversion = \"0.3.0\"\n\n[dependencies]\ncore = \"^1\"\nutils = { path = \"../utils\" }\n\n[dev-dependencies]\ntest-helpers = \"^1\"\n",
+        )?;
+        let package = read_local_package("local", temp_dir.path())?;
+        assert_eq!(package.version.0, semver::Version::parse("0.3.0")?);
+        assert_eq!(package.dependencies.keys().collect::<Vec<_>>(), vec!["core", "utils"]);
+        assert_eq!(package.dependencies["utils"].version, semver::VersionReq::STAR);
+        assert_eq!(package.dependencies["utils"].path.as_deref(), Some("../utils"));
+
+        std::fs::write(temp_dir.path().join("manifest.toml"), "[dependencies]\n")?;
+        assert!(read_local_package("local", temp_dir.path()).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_dependencies() -> Result<(), Error> {
+        for (content, expected) in [
+            ("core = 1", "Invalid dependency 'core'"),
+            ("core = \"foo\"", "invalid version requirement 'foo'"),
+            ("core = { optional = true }", "either 'version', 'path' or 'git' must be specified"),
+            ("core = { path = \"../core\", registry = \"default\" }", "only one of 'registry', 'path' and 'git'"),
+            ("core = { git = \"https://host/core.git\", tag = \"1.0.0\", rev = \"abc\" }", "only one of 'tag', 'branch' and 'rev'"),
+            ("core = { version = \"^1\", branch = \"main\" }", "require 'git'"),
+            ("core = { version = \"^1\", features = [] }", "unknown field `features`"),
+        ] {
+            let manifest: toml::Table = toml::from_str(&format!("[dependencies]\n{content}"))?;
+            let error = parse_dependencies(&manifest, "dependencies").unwrap_err().to_string();
+            assert!(error.contains(expected), "'{error}' does not contain '{expected}'");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_constraints() -> Result<(), Error> {
+        let manifest: toml::Table = toml::from_str("[constraints]\ncore = \"<2\"\n")?;
+        assert_eq!(parse_constraints(&manifest)?["core"], semver::VersionReq::parse("<2")?);
+
+        let manifest: toml::Table = toml::from_str("[constraints]\ncore = { version = \"<2\" }\n")?;
+        let error = parse_constraints(&manifest).unwrap_err().to_string();
+        assert!(error.contains("Invalid constraint 'core'"), "{error}");
+        Ok(())
+    }
+
+    #[test]
+    fn test_serialize_roundtrip() -> Result<(), Error> {
+        let simple = Dependency::new(semver::VersionReq::parse("^1")?);
+        assert_eq!(serde_json::to_string(&simple)?, "\"^1\"");
+
+        let detailed = Dependency {
+            optional: true,
+            ..simple
+        };
+        let json = serde_json::to_string(&detailed)?;
+        assert_eq!(serde_json::from_str::<Dependency>(&json)?, detailed);
+        Ok(())
+    }
+}
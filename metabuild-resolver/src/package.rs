@@ -1,3 +1,4 @@
+use crate::manifest::Dependency;
 use indexmap::IndexMap;
 use resolvo::VersionSet;
 use serde::de::{self, Visitor};
@@ -68,17 +69,34 @@ impl FromStr for Version {
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
-pub struct VersionReq(pub semver::VersionReq);
+pub struct VersionReq {
+    pub req: semver::VersionReq,
+    /// Pre-release versions also match if the requirement matches their release version,
+    /// otherwise only requirements which name a pre-release of the same version match them
+    pub pre: bool,
+}
 
 impl VersionReq {
+    pub fn new(req: semver::VersionReq, pre: bool) -> Self {
+        Self { req, pre }
+    }
+
     pub fn matches(&self, version: &Version) -> bool {
-        self.0.matches(&version.0)
+        if self.req.matches(&version.0) {
+            return true;
+        }
+        if !self.pre || version.0.pre.is_empty() {
+            return false;
+        }
+        let mut release = version.0.clone();
+        release.pre = semver::Prerelease::EMPTY;
+        self.req.matches(&release)
     }
 }
 
 impl Display for VersionReq {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.0.to_string())
+        write!(f, "{}", self.req)
     }
 }
 
@@ -87,7 +105,7 @@ impl Serialize for VersionReq {
     where
         S: Serializer,
     {
-        self.0.to_string().serialize(serializer)
+        self.req.to_string().serialize(serializer)
     }
 }
 
@@ -110,7 +128,7 @@ impl<'de> Deserialize<'de> for VersionReq {
                 E: de::Error,
             {
                 semver::VersionReq::parse(value)
-                    .map(VersionReq)
+                    .map(VersionReq::from)
                     .map_err(de::Error::custom)
                 /*SemverVersion::parse(value)
                 .map(Version)
@@ -132,22 +150,25 @@ impl VersionSet for VersionReq {
 
 impl From<semver::VersionReq> for VersionReq {
     fn from(value: semver::VersionReq) -> Self {
-        Self(value)
+        Self::new(value, false)
     }
 }
 
 impl FromStr for VersionReq {
     type Err = semver::Error;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        Ok(Self(semver::VersionReq::parse(s)?))
+        Ok(Self::new(semver::VersionReq::parse(s)?, false))
     }
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Package {
     pub name: String,
     pub version: Version,
-    pub dependencies: IndexMap<String, VersionReq>,
+    pub dependencies: IndexMap<String, Dependency>,
+    /// Version restrictions for packages in the graph which are not required by the package
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub constraints: IndexMap<String, semver::VersionReq>,
 }
 
 impl Package {
@@ -156,12 +177,40 @@ impl Package {
             name: name.into(),
             version: version.parse().unwrap(),
             dependencies: IndexMap::new(),
+            constraints: IndexMap::new(),
         }
     }
 
     pub fn add_dependency(&mut self, name: &str, range: &str) -> &mut Self {
         self.dependencies
-            .insert(name.to_string(), range.parse().unwrap());
+            .insert(name.to_string(), Dependency::new(range.parse().unwrap()));
         self
     }
+
+    pub fn set_dependencies(&mut self, dependencies: IndexMap<String, Dependency>) -> &mut Self {
+        self.dependencies = dependencies;
+        self
+    }
+
+    pub fn set_constraints(&mut self, constraints: IndexMap<String, semver::VersionReq>) -> &mut Self {
+        self.constraints = constraints;
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prerelease_matches() -> Result<(), semver::Error> {
+        let rc: Version = "2.0.0-rc.1".parse()?;
+        let release: Version = "2.0.0".parse()?;
+        assert!(!VersionReq::new(semver::VersionReq::parse("^2")?, false).matches(&rc));
+        assert!(VersionReq::new(semver::VersionReq::parse("^2")?, true).matches(&rc));
+        assert!(VersionReq::new(semver::VersionReq::parse("^2")?, true).matches(&release));
+        assert!(!VersionReq::new(semver::VersionReq::parse("<2")?, true).matches(&rc));
+        assert!(VersionReq::new(semver::VersionReq::parse("=2.0.0-rc.1")?, false).matches(&rc));
+        Ok(())
+    }
 }
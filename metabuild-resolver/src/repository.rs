@@ -15,6 +15,18 @@ pub struct BareRepository {
 pub enum RefType {
     Tag(String),
     Branch(String),
+    /// Any revision git understands, e.g. a commit hash
+    Commit(String),
+}
+
+impl RefType {
+    fn revision(&self) -> String {
+        match self {
+            RefType::Tag(tag) => format!("refs/tags/{}", tag),
+            RefType::Branch(branch) => format!("refs/heads/{}", branch),
+            RefType::Commit(revision) => revision.clone(),
+        }
+    }
 }
 
 impl BareRepository {
@@ -58,6 +70,19 @@ impl BareRepository {
         Ok(Self { repo, temp_dir })
     }
 
+    /// Opens an existing local working copy without fetching from the remote
+    pub fn open(url: &str, storage_path: &Path) -> Result<Self, Error> {
+        let repo = Repository::open_bare(storage_path)
+            .map_err(|_| anyhow::anyhow!("No local copy of repository {url} found in {:?}", storage_path))?;
+        let remote_url = repo.find_remote("origin")?.url().map(String::from);
+        if remote_url.as_deref() != Some(url) {
+            return Err(anyhow::anyhow!("Local copy in {:?} does not belong to repository {url}", storage_path));
+        }
+
+        debug!("Opened existing bare repository at {:?} without fetching", storage_path);
+        Ok(Self { repo, temp_dir: None })
+    }
+
     fn clone(url: &str, path: &Path) -> Result<Repository, Error> {
         std::fs::create_dir_all(&path)?;
         let git_config = make_git_config()?;
@@ -65,6 +90,8 @@ impl BareRepository {
         let fetch_options = make_fetch_options(&auth, &git_config);
         let mut repo_builder = RepoBuilder::new();
         repo_builder.fetch_options(fetch_options);
+        // Mirror all branches like later fetches do, not only the default branch
+        repo_builder.remote_create(|repo, name, url| repo.remote_with_fetch(name, url, "+refs/heads/*:refs/heads/*"));
         repo_builder.bare(true).clone(url, &path).map_err(anyhow::Error::from)
     }
 
@@ -78,11 +105,19 @@ impl BareRepository {
         Ok(tags)
     }
 
+    /// Returns the id of the commit the reference points to
+    pub fn get_commit(&self, ref_type: &RefType) -> Result<String, Error> {
+        let revision = ref_type.revision();
+        let commit = self
+            .repo
+            .revparse_single(&revision)
+            .and_then(|object| object.peel_to_commit())
+            .map_err(|e| anyhow::anyhow!("Could not find revision '{revision}': {e}"))?;
+        Ok(commit.id().to_string())
+    }
+
     pub fn get_file(&self, ref_type: &RefType, path: &Path) -> Result<Vec<u8>, Error> {
-        let reference = match ref_type {
-            RefType::Tag(tag) => format!("refs/tags/{}", tag),
-            RefType::Branch(branch) => format!("refs/heads/{}", branch),
-        };
+        let reference = ref_type.revision();
 
         let tree_id = self.repo.revparse_single(&reference)?.peel_to_tree()?.id();
         let tree = self.repo.find_tree(tree_id)?;
@@ -94,7 +129,7 @@ impl BareRepository {
 
     pub fn update_file_and_commit(&self, ref_type: &RefType, file_path: &str, file_contents: &[u8], commit_message: &str) -> Result<Oid, Error> {
         let reference = match ref_type {
-            RefType::Tag(_) => {
+            RefType::Tag(_) | RefType::Commit(_) => {
                 return Err(anyhow::anyhow!("Cannot update file and commit on a tag reference"));
             }
             RefType::Branch(branch) => format!("refs/heads/{}", branch),
@@ -123,7 +158,7 @@ impl BareRepository {
 
     pub fn revert(&mut self, ref_type: &RefType) -> Result<(), Error> {
         let reference = match ref_type {
-            RefType::Tag(_) => {
+            RefType::Tag(_) | RefType::Commit(_) => {
                 return Err(anyhow::anyhow!("No need to revert a tag reference"));
             }
             RefType::Branch(branch) => format!("refs/heads/{}", branch),
@@ -146,7 +181,7 @@ impl BareRepository {
 
     pub fn push(&self, ref_type: &RefType) -> Result<(), Error> {
         let reference = match ref_type {
-            RefType::Tag(_) => {
+            RefType::Tag(_) | RefType::Commit(_) => {
                 return Err(anyhow::anyhow!("Cannot update file and commit on a tag reference"));
             }
             RefType::Branch(branch) => format!("refs/heads/{}", branch),
@@ -1,3 +1,7 @@
+use crate::commands::index::ListingFormat;
+use crate::commands::install::ResolutionStrategy;
+use crate::commands::outdated::OutputFormat;
+use crate::commands::tree::TreeFormat;
 use clap::{Parser, Subcommand};
 use std::path::PathBuf;
 
@@ -15,6 +19,101 @@ pub enum Commands {
         /// Manifest file (defaults to manifest.toml)
         #[arg(short, long)]
         file: Option<PathBuf>,
+        /// Install exactly the versions recorded in manifest.lock
+        #[arg(long, default_value = "false")]
+        locked: bool,
+        /// Resolve and install only from locally cached data
+        #[arg(long, default_value = "false")]
+        offline: bool,
+        /// Which versions are preferred, `lowest` checks the lower bounds of all requirements
+        #[arg(long, value_enum, default_value = "highest")]
+        resolution: ResolutionStrategy,
+        /// Allow pre-release versions of all packages
+        #[arg(long, default_value = "false")]
+        pre: bool,
+        /// Format of the report if the dependencies cannot be resolved
+        #[arg(long, value_enum, default_value = "text")]
+        error_format: OutputFormat,
+    },
+    /// Display the resolved dependency graph
+    Tree {
+        /// Manifest file (defaults to manifest.toml)
+        #[arg(short, long)]
+        file: Option<PathBuf>,
+        /// Show the packages which depend on the given package
+        #[arg(short, long, value_name = "PACKAGE")]
+        invert: Option<String>,
+        /// Maximum depth of the displayed tree
+        #[arg(short, long)]
+        depth: Option<usize>,
+        /// Repeat the dependencies of packages which were already shown instead of marking them with (*)
+        #[arg(long, default_value = "false")]
+        no_dedupe: bool,
+        /// Output format
+        #[arg(long, value_enum, default_value = "text")]
+        format: TreeFormat,
+        /// Resolve only from locally cached data
+        #[arg(long, default_value = "false")]
+        offline: bool,
+    },
+    /// Explain why a package is part of the dependency graph and how its version was selected
+    Why {
+        /// Package to explain
+        package: String,
+        /// Manifest file (defaults to manifest.toml)
+        #[arg(short, long)]
+        file: Option<PathBuf>,
+        /// Resolve only from locally cached data
+        #[arg(long, default_value = "false")]
+        offline: bool,
+    },
+    /// List dependencies for which newer versions are available
+    Outdated {
+        /// Manifest file (defaults to manifest.toml)
+        #[arg(short, long)]
+        file: Option<PathBuf>,
+        /// Output format
+        #[arg(long, value_enum, default_value = "text")]
+        format: OutputFormat,
+        /// Exit with code 1 if any dependency is outdated
+        #[arg(long, default_value = "false")]
+        exit_code: bool,
+        /// Resolve only from locally cached data
+        #[arg(long, default_value = "false")]
+        offline: bool,
+    },
+    /// Update the given dependencies while keeping all other installed versions
+    Update {
+        /// Packages to update
+        #[arg(required = true)]
+        packages: Vec<String>,
+        /// Also update the dependencies of the given packages
+        #[arg(short, long, default_value = "false")]
+        recursive: bool,
+        /// Allow pre-release versions of all packages
+        #[arg(long, default_value = "false")]
+        pre: bool,
+        /// Manifest file (defaults to manifest.toml)
+        #[arg(short, long)]
+        file: Option<PathBuf>,
+        /// Resolve only from locally cached data
+        #[arg(long, default_value = "false")]
+        offline: bool,
+        /// Format of the report if the dependencies cannot be resolved
+        #[arg(long, value_enum, default_value = "text")]
+        error_format: OutputFormat,
+    },
+    /// Publish the package described by a manifest file
+    Publish {
+        /// Manifest file (defaults to manifest.toml)
+        #[arg(short, long)]
+        file: Option<PathBuf>,
+        /// Overwrite an existing version
+        #[arg(long, default_value = "false")]
+        force: bool,
+        /// Registry of the manifest which the package is published to
+        #[arg(long, default_value = "default")]
+        registry: String,
     },
     /// Run a metabuild script
     Run {
@@ -29,8 +128,17 @@ pub enum Commands {
         #[command(subcommand)]
         command: IndexCommands,
     },
-    /// Update metabuild
-    Update,
+    /// Manage the package cache
+    Cache {
+        #[command(subcommand)]
+        command: CacheCommands,
+    },
+    /// Manage the metabuild installation
+    #[command(name = "self")]
+    SelfCommand {
+        #[command(subcommand)]
+        command: SelfCommands,
+    },
     /// Interact with metabuild configuration
     Config {
         #[command(subcommand)]
@@ -44,7 +152,7 @@ pub enum IndexCommands {
     AddGit {
         /// Name
         name: String,
-        /// Url (SSH format)
+        /// Url (SSH or HTTPS format)
         url: String,
         /// Index repository Url
         #[arg(short, long)]
@@ -64,6 +172,29 @@ pub enum IndexCommands {
         #[arg(short, long)]
         index: Option<String>,
     },
+    /// Add a static HTTP file server source to the index
+    AddHttp {
+        /// Name
+        name: String,
+        /// Url of the version directories, `{version}` is replaced by the version
+        url: String,
+        /// How the server lists the available versions
+        #[arg(long, value_enum, default_value = "json")]
+        listing: ListingFormat,
+        /// Index repository Url
+        #[arg(short, long)]
+        index: Option<String>,
+    },
+    /// Add a local package directory to the index
+    AddLocal {
+        /// Name
+        name: String,
+        /// Directory with one subdirectory per version (path or file:// URL)
+        path: String,
+        /// Index repository Url
+        #[arg(short, long)]
+        index: Option<String>,
+    },
     /// Remove a source from the index
     Remove {
         /// Name
@@ -72,6 +203,26 @@ pub enum IndexCommands {
         #[arg(short, long)]
         index: Option<String>,
     },
+    /// Mark a version as yanked so that it is not selected for new resolutions
+    Yank {
+        /// Name
+        name: String,
+        /// Version
+        version: semver::Version,
+        /// Index repository Url
+        #[arg(short, long)]
+        index: Option<String>,
+    },
+    /// Remove the yanked mark from a version
+    Unyank {
+        /// Name
+        name: String,
+        /// Version
+        version: semver::Version,
+        /// Index repository Url
+        #[arg(short, long)]
+        index: Option<String>,
+    },
     /// Revert the local index changes
     Revert {
         /// Index repository Url
@@ -92,6 +243,26 @@ pub enum IndexCommands {
     }
 }
 
+#[derive(Subcommand)]
+pub enum CacheCommands {
+    /// Show location and size of the package cache
+    Info,
+    /// Remove all entries from the package cache
+    Clean,
+    /// Remove entries which have not been used recently
+    Prune {
+        /// Maximum age in days
+        #[arg(short, long, default_value = "30")]
+        days: u64,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum SelfCommands {
+    /// Update metabuild to the latest release
+    Update,
+}
+
 #[derive(Subcommand)]
 pub enum ConfigCommands {
     /// Set a configuration value
@@ -137,6 +308,27 @@ pub enum ConfigCommands {
         #[arg(short, long, default_value = "false")]
         local: bool,
     },
+    /// Set an access token for git repositories on an HTTPS host
+    SetGitToken {
+        /// Git server host name
+        host: String,
+        /// Access token, optionally in the format 'username:token'
+        token: String,
+        /// Set in local configuration file instead of global one
+        #[arg(short, long, default_value = "false")]
+        local: bool,
+    },
+    GetGitToken {
+        /// Git server host name
+        host: String,
+    },
+    RemoveGitToken {
+        /// Git server host name
+        host: String,
+        /// Set in local configuration file instead of global one
+        #[arg(short, long, default_value = "false")]
+        local: bool,
+    },
     /// Display all set configuration values
     Show,
     /// List all available configuration names
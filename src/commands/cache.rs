@@ -0,0 +1,39 @@
+use anyhow::Error;
+use colored::*;
+use metabuild_resolver::cache::{Cache, CacheUsage};
+use std::time::Duration;
+
+fn format_size(size: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut value = size as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{value:.1} {}", UNITS[unit])
+}
+
+fn print_usage(name: &str, usage: &CacheUsage) {
+    println!("  {name}: {} entries, {}", usage.entries, format_size(usage.size));
+}
+
+pub fn info(cache: &Cache) -> Result<(), Error> {
+    let info = cache.info()?;
+    println!("{} {}", "Package cache".bright_green().bold(), cache.root().display());
+    print_usage("Git repositories", &info.git);
+    print_usage("Files", &info.blobs);
+    Ok(())
+}
+
+pub fn clean(cache: &Cache) -> Result<(), Error> {
+    cache.clean()?;
+    println!("Removed all entries from the package cache");
+    Ok(())
+}
+
+pub fn prune(cache: &Cache, days: u64) -> Result<(), Error> {
+    let removed = cache.prune(Duration::from_secs(days * 24 * 60 * 60))?;
+    println!("Removed {removed} entries which were not used within {days} days");
+    Ok(())
+}
@@ -17,7 +17,10 @@ pub struct Config {
 #[derive(Serialize, Deserialize, Reflect, Default)]
 pub struct ConfigData {
     pub index: Option<String>,
+    pub offline: Option<bool>,
     pub artifactory_token: HashMap<String, String>,
+    #[serde(default)]
+    pub git_token: HashMap<String, String>,
 }
 
 pub enum ConfigScope {
@@ -81,6 +84,12 @@ impl Config {
                 target.index = Some(value.to_owned());
                 self.write_and_update()
             },
+            "offline" => {
+                let value = value.parse::<bool>().map_err(|_| anyhow!("Config 'offline' must be 'true' or 'false'"))?;
+                println!("Set config 'offline'");
+                target.offline = Some(value);
+                self.write_and_update()
+            },
             _ => {
                 println!("Invalid config key");
                 Err(anyhow!("Invalid config key"))
@@ -99,11 +108,25 @@ impl Config {
         self.write_and_update()
     }
 
+    pub fn set_git_token(&mut self, host: &str, token: &str, scope: ConfigScope) -> Result<(), Error> {
+        let target = match scope {
+            ConfigScope::Global => &mut self.global,
+            ConfigScope::Local => &mut self.local,
+        };
+
+        println!("Set git token for host '{}'", host);
+        target.git_token.insert(host.to_owned(), token.to_owned());
+        self.write_and_update()
+    }
+
     pub fn get(&mut self, key: &str) -> Result<&str, Error> {
         match key {
             "index" => {
                 self.merged.index.as_ref().map(String::as_str).ok_or(anyhow!("Config 'index' is not set"))
             },
+            "offline" => {
+                self.merged.offline.map(|v| if v { "true" } else { "false" }).ok_or(anyhow!("Config 'offline' is not set"))
+            },
             _ => {
                 println!("Invalid config key");
                 Err(anyhow!("Invalid config key"))
@@ -119,6 +142,14 @@ impl Config {
             .ok_or(anyhow!("Artifactory token for url '{}' is not set", url))
     }
 
+    pub fn get_git_token(&mut self, host: &str) -> Result<&str, Error> {
+        self.merged
+            .git_token
+            .get(host)
+            .map(String::as_str)
+            .ok_or(anyhow!("Git token for host '{}' is not set", host))
+    }
+
     pub fn remove(&mut self, key: &str, scope: ConfigScope) -> Result<(), Error> {
         let target = match scope {
             ConfigScope::Global => &mut self.global,
@@ -136,6 +167,16 @@ impl Config {
                     Ok(())
                 }
             },
+            "offline" => {
+                if target.offline.is_some() {
+                    println!("Removed config 'offline'");
+                    target.offline = None;
+                    self.write_and_update()
+                } else {
+                    println!("Nothing to remove");
+                    Ok(())
+                }
+            },
             _ => {
                 println!("Invalid config key");
                 Err(anyhow!("Invalid config key"))
@@ -158,24 +199,44 @@ impl Config {
         }
     }
 
+    pub fn remove_git_token(&mut self, host: &str, scope: ConfigScope) -> Result<(), Error> {
+        let target = match scope {
+            ConfigScope::Global => &mut self.global,
+            ConfigScope::Local => &mut self.local,
+        };
+
+        if target.git_token.remove(host).is_some() {
+            println!("Removed git token for host '{}'", host);
+            self.write_and_update()
+        } else {
+            println!("Nothing to remove");
+            Ok(())
+        }
+    }
+
     pub fn show(&mut self) -> Result<(), Error> {
         if let Some(type_info) = self.merged.get_represented_type_info() {
             if let TypeInfo::Struct(struct_info) = type_info {
                 for (i, reflect_value) in self.merged.iter_fields().enumerate() {
                     let field_name = struct_info.field_at(i).expect("Failed to retrieve field reflection info").name();
-                    if let Some(option) = reflect_value.downcast_ref::<Option<String>>() {
-                        if let Some(value) = option.as_ref() {
-                            let figment_value = self.figment.find_value(field_name).expect("Failed to retrieve figment value");
-                            let figment_source = self.figment.get_metadata(figment_value.tag())
-                                .expect("Failed to retrieve figment metadata")
-                                .source.as_ref().expect("Failed to retrieve figment source");
-                            println!(
-                                "{}: {} ({})",
-                                field_name,
-                                value,
-                                figment_source
-                            );
-                        }
+                    let value = if let Some(option) = reflect_value.downcast_ref::<Option<String>>() {
+                        option.clone()
+                    } else if let Some(option) = reflect_value.downcast_ref::<Option<bool>>() {
+                        option.map(|v| v.to_string())
+                    } else {
+                        None
+                    };
+                    if let Some(value) = value {
+                        let figment_value = self.figment.find_value(field_name).expect("Failed to retrieve figment value");
+                        let figment_source = self.figment.get_metadata(figment_value.tag())
+                            .expect("Failed to retrieve figment metadata")
+                            .source.as_ref().expect("Failed to retrieve figment source");
+                        println!(
+                            "{}: {} ({})",
+                            field_name,
+                            value,
+                            figment_source
+                        );
                     }
                 }
             }
@@ -185,7 +246,8 @@ impl Config {
     }
 
     pub fn list(&self) -> Result<(), Error> {
-        println!("index: URL of the package index; can be overridden in the project's manifest.toml; only SSH protocol is supported");
+        println!("index: URL of the package index; can be overridden in the project's manifest.toml; SSH and HTTPS are supported");
+        println!("offline: if 'true', resolve and install dependencies only from locally cached data");
         Ok(())
     }
 }
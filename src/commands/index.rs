@@ -1,7 +1,25 @@
 use anyhow::Error;
 use colored::*;
 use itertools::Itertools;
-use metabuild_resolver::index::{Entry, Index};
+use metabuild_resolver::index::{Entry, Index, Listing};
+
+/// Version listing of an HTTP source, see `Listing`
+#[derive(Clone, Copy, clap::ValueEnum)]
+pub enum ListingFormat {
+    /// `versions.json` file with an array of versions
+    Json,
+    /// HTML directory listing
+    Html,
+}
+
+impl From<ListingFormat> for Listing {
+    fn from(format: ListingFormat) -> Self {
+        match format {
+            ListingFormat::Json => Listing::Json,
+            ListingFormat::Html => Listing::Html,
+        }
+    }
+}
 
 pub fn add_git(index: &mut Index, name: &str, url: &str) -> Result<(), Error> {
     index.add_entry(name, Entry::Git { url: url.to_string() })
@@ -15,10 +33,26 @@ pub fn add_artifactory(index: &mut Index, name: &str, server: &str, repo: &str,
     })
 }
 
+pub fn add_http(index: &mut Index, name: &str, url: &str, listing: ListingFormat) -> Result<(), Error> {
+    index.add_entry(name, Entry::Http { url: url.to_string(), listing: listing.into() })
+}
+
+pub fn add_local(index: &mut Index, name: &str, path: &str) -> Result<(), Error> {
+    index.add_entry(name, Entry::Local { path: path.to_string() })
+}
+
 pub fn remove(index: &mut Index, name: &str) -> Result<(), Error> {
     index.remove_entry(name)
 }
 
+pub fn yank(index: &mut Index, name: &str, version: &semver::Version) -> Result<(), Error> {
+    index.set_yanked(name, version, true)
+}
+
+pub fn unyank(index: &mut Index, name: &str, version: &semver::Version) -> Result<(), Error> {
+    index.set_yanked(name, version, false)
+}
+
 pub fn revert(index: &mut Index) -> Result<(), Error> {
     index.revert()
 }
@@ -33,6 +67,14 @@ pub fn list(index: &Index) -> Result<(), Error> {
             match index.get_entry(entry) {
                 Ok(Entry::Git { url }) => println!("{}\n  {} {}", entry.bright_green().bold(), "(Git)".bright_yellow(), url),
                 Ok(Entry::Artifactory { server, repo, path }) => println!("{}\n  {} {}/{}/{}", entry.bright_green().bold(), "(Artifactory)".bright_yellow(), server, repo, path),
+                Ok(Entry::Http { url, listing }) => {
+                    let listing = match listing {
+                        Listing::Json => "versions.json",
+                        Listing::Html => "HTML listing",
+                    };
+                    println!("{}\n  {} {} ({})", entry.bright_green().bold(), "(HTTP)".bright_yellow(), url, listing)
+                },
+                Ok(Entry::Local { path }) => println!("{}\n  {} {}", entry.bright_green().bold(), "(Local)".bright_yellow(), path),
                 Err(e) => eprintln!("{}: {}", "Error".red().bold(), e),
             }
         }
@@ -1,142 +1,1331 @@
 use crate::commands::config::ConfigData;
 use crate::git;
+use crate::lockfile::{LockedPackage, LockedSource, Lockfile};
 use anyhow::Error;
 use flate2::read::GzDecoder;
-use metabuild_resolver::{inventory::Inventory, index::{Index, Entry}, solve};
-use std::collections::HashMap;
+use itertools::Itertools;
+use log::warn;
+use metabuild_resolver::cache::{sha256_file, Cache};
+use metabuild_resolver::inventory::{find_artifactory_token, Inventory, ResolutionMode};
+use metabuild_resolver::manifest::{
+    parse_constraints, parse_dependencies as parse_dependency_table, read_git_package, read_local_package, Dependency,
+    GitSource, Patch,
+};
+use metabuild_resolver::package::Package;
+use metabuild_resolver::{index::{http_version_url, local_path, Entry, Registries, VersionMetadata}, solve};
+use serde::Deserialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::fs::File;
 use std::io;
-use std::path::Path;
+use std::path::{Component, Path, PathBuf};
 use tar::Archive;
-use tempfile::TempDir;
 use url::Url;
 
-struct GitInstaller;
-impl GitInstaller {
-    fn install(&self, url: &str, name: &str, version: &str, target_path: &Path) -> Result<(), Error> {
+/// Name of the file which records the installed package inside its directory
+const INSTALL_RECORD_FILE: &str = ".mb-package.toml";
+
+struct GitInstaller<'a> {
+    cache: &'a Cache,
+    offline: bool,
+}
+
+impl GitInstaller<'_> {
+    fn is_cached(&self, url: &str, revision: &str) -> bool {
+        git::has_cached_revision(url, revision, self.cache)
+    }
+
+    /// Installs the given revision (tag or commit) and returns the id of the checked out commit
+    fn install(&self, url: &str, name: &str, revision: &str, target_path: &Path) -> Result<String, Error> {
         let target_path = target_path.join(name);
-        let repo = git::clone(url, target_path.as_path())?;
-        git::checkout(&repo, version.to_string().as_str())
+        let repo = git::clone_cached(url, revision, self.cache, self.offline, target_path.as_path())?;
+        git::checkout(&repo, revision)?;
+        let commit = repo.head()?.peel_to_commit()?.id().to_string();
+        Ok(commit)
     }
 }
 
-struct ArtifactoryInstaller;
-impl ArtifactoryInstaller {
-    fn download_file(url: &str, target_path: &Path) -> Result<(), Error> {
+struct ArtifactoryLocation<'a> {
+    server: &'a str,
+    repo: &'a str,
+    path: &'a str,
+}
+
+/// Locations of the files of a package version
+struct ArchiveUrls {
+    manifest: Url,
+    archive: Url,
+}
+
+impl ArchiveUrls {
+    fn http(url: &str, version: &str) -> Result<Self, Error> {
+        let version_url = http_version_url(url, version);
+        Ok(Self {
+            manifest: Url::parse(&format!("{version_url}/manifest.toml"))?,
+            archive: Url::parse(&format!("{version_url}/package.tar.gz"))?,
+        })
+    }
+}
+
+impl ArtifactoryLocation<'_> {
+    fn archive_urls(&self, version: &str) -> Result<ArchiveUrls, Error> {
+        Ok(ArchiveUrls { manifest: self.file_url(version, "manifest.toml")?, archive: self.file_url(version, "package.tar.gz")? })
+    }
+
+    fn file_url(&self, version: &str, file: &str) -> Result<Url, Error> {
+        Ok(Url::parse(format!("{}/{}/{}/{version}/{file}", self.server, self.repo, self.path).as_str())?)
+    }
+
+    fn storage_url(&self, version: &str, file: &str) -> Result<Url, Error> {
+        Ok(Url::parse(format!("{}/api/storage/{}/{}/{version}/{file}", self.server, self.repo, self.path).as_str())?)
+    }
+}
+
+#[derive(Deserialize)]
+struct StorageInfo {
+    #[serde(default)]
+    checksums: HashMap<String, String>,
+}
+
+/// Installs packages which are distributed as `manifest.toml` and `package.tar.gz`, i.e. from
+/// Artifactory and HTTP servers
+struct ArchiveInstaller<'a> {
+    cache: &'a Cache,
+    tokens: &'a HashMap<String, String>,
+    offline: bool,
+}
+
+impl ArchiveInstaller<'_> {
+    fn cached_digest(&self, url: &Url, expected_sha256: Option<&str>) -> Option<String> {
+        match expected_sha256 {
+            Some(digest) if self.cache.get_blob(digest).is_some() => Some(digest.to_string()),
+            Some(digest) => self.cache.lookup(url.as_str()).filter(|d| d == digest),
+            None => self.cache.lookup(url.as_str()),
+        }
+    }
+
+    fn is_cached(&self, urls: &ArchiveUrls, expected_sha256: Option<&str>) -> bool {
+        self.cached_digest(&urls.manifest, None).is_some() && self.cached_digest(&urls.archive, expected_sha256).is_some()
+    }
+
+    fn get(&self, url: &Url) -> Result<ureq::Response, Error> {
         let agent = ureq::AgentBuilder::new().build();
-        let response = match agent.get(url).call() {
-            Ok(response) => response,
+        let mut request = agent.get(url.as_str());
+        if let Some(token) = find_artifactory_token(self.tokens, url.as_str()) {
+            request = request.set("Authorization", format!("Bearer {token}").as_str());
+        }
+
+        match request.call() {
+            Ok(response) => Ok(response),
             Err(ureq::Error::Status(code, response)) => {
-                return Err(anyhow::anyhow!("Server returned code {}: {}", code, response.status_text()));
+                Err(anyhow::anyhow!("Server returned code {}: {}", code, response.status_text()))
             }
             Err(_) => {
-                return Err(anyhow::anyhow!("Unknown error"));
+                Err(anyhow::anyhow!("Unknown error"))
             }
-        };
+        }
+    }
+
+    /// Downloads the file and returns the SHA-256 checksum reported by the server, if any
+    fn download_file(&self, url: &Url, target_path: &Path) -> Result<Option<String>, Error> {
+        let response = self.get(url)?;
+        let checksum = response.header("X-Checksum-Sha256").map(str::to_lowercase);
 
         let mut output_file = File::create(target_path)?;
         match std::io::copy(&mut response.into_reader(), &mut output_file) {
-            Ok(_) => Ok(()),
+            Ok(_) => Ok(checksum),
             Err(e) => {
-                return Err(anyhow::anyhow!("Could not write to file: {e}"));
+                Err(anyhow::anyhow!("Could not write to file: {e}"))
             }
         }
     }
 
-    fn install(&self, server: &str, repo: &str, path: &str, name: &str, version: &str, target_path: &Path) -> Result<(), Error> {
-        std::fs::create_dir_all(target_path.join(name))?;
+    /// Queries the SHA-256 checksum of a file via the Artifactory storage API
+    fn query_checksum(&self, url: &Url) -> Option<String> {
+        let info: StorageInfo = self.get(url).ok()?.into_json().ok()?;
+        info.checksums.get("sha256").map(|c| c.to_lowercase())
+    }
+
+    /// Returns the cached blob for the given url of the package version. The file is only
+    /// downloaded if it is not cached yet or if the cached blob does not have the expected
+    /// digest. Downloads are verified against `server_sha256` before they are added to the cache.
+    fn fetch_cached<F>(&self, url: &Url, name: &str, version: &str, expected_sha256: Option<&str>, server_sha256: F) -> Result<PathBuf, Error>
+    where
+        F: FnOnce() -> Option<String>,
+    {
+        let digest = match self.cached_digest(url, expected_sha256) {
+            Some(digest) => digest,
+            None if self.offline => {
+                return Err(anyhow::anyhow!("{url} is not available in the package cache"));
+            },
+            None => self.cache.store(url.as_str(), |path| {
+                let header_sha256 = self.download_file(url, path)?;
+                if let Some(server_sha256) = header_sha256.or_else(server_sha256) {
+                    verify_checksum(path, name, version, &server_sha256)
+                        .map_err(|e| anyhow::anyhow!("{e} (downloaded from {url})"))?;
+                } else {
+                    warn!("Server did not provide a checksum for {url}");
+                }
+                Ok(())
+            })?,
+        };
+
+        self.cache.get_blob(&digest)
+            .ok_or(anyhow::anyhow!("Blob {digest} is missing in the package cache"))
+    }
+
+    /// Reads the SHA-256 checksum from a `<file>.sha256` file next to the given url
+    fn query_checksum_file(&self, url: &Url) -> Option<String> {
+        let checksum_url = Url::parse(&format!("{url}.sha256")).ok()?;
+        let content = self.get(&checksum_url).ok()?.into_string().ok()?;
+        content.split_whitespace().next().map(str::to_lowercase)
+    }
+
+    fn install_artifactory(&self, location: &ArtifactoryLocation, name: &str, version: &str, expected_sha256: Option<&str>, target_path: &Path) -> Result<String, Error> {
+        self.install(&location.archive_urls(version)?, name, version, expected_sha256, target_path, || {
+            location.storage_url(version, "package.tar.gz").ok().and_then(|u| self.query_checksum(&u))
+        })
+    }
+
+    fn install_http(&self, url: &str, name: &str, version: &str, expected_sha256: Option<&str>, target_path: &Path) -> Result<String, Error> {
+        let urls = ArchiveUrls::http(url, version)?;
+        self.install(&urls, name, version, expected_sha256, target_path, || self.query_checksum_file(&urls.archive))
+    }
+
+    /// Installs the given version and returns the SHA-256 digest of the package tarball. If an
+    /// expected digest is given the tarball is verified before unpacking.
+    fn install<F>(&self, urls: &ArchiveUrls, name: &str, version: &str, expected_sha256: Option<&str>, target_path: &Path, server_sha256: F) -> Result<String, Error>
+    where
+        F: FnOnce() -> Option<String>,
+    {
+        let target_path = target_path.join(name);
+        std::fs::create_dir_all(&target_path)?;
 
         // Fetch manifest first
-        let url = Url::parse(format!("{server}/{repo}/{path}/{version}/manifest.toml").as_str())?;
-        let output_path = target_path.join(name).join("manifest.toml");
-        Self::download_file(url.as_str(), output_path.as_path())?;
+        let manifest_path = self.fetch_cached(&urls.manifest, name, version, None, || None)?;
+        std::fs::copy(manifest_path, target_path.join("manifest.toml"))?;
 
         // Then fetch the package tarball
-        let url = Url::parse(format!("{server}/{repo}/{path}/{version}/package.tar.gz").as_str())?;
-        let t = TempDir::new()?;
-        let output_path = t.path().join("package.tar.gz");
-        Self::download_file(url.as_str(), output_path.as_path())?;
+        let archive_path = self.fetch_cached(&urls.archive, name, version, expected_sha256, server_sha256)?;
 
-        // Unpack the tarball
-        let archive_file = File::open(&output_path)?;
-        let target_path = target_path.join(name);
-        let decoder = GzDecoder::new(archive_file);
-        let mut archive = Archive::new(decoder);
-        archive.unpack(target_path).map_err(|e| anyhow::anyhow!("Failed to unpack archive: {e}"))
+        // Always hash the file which is actually unpacked, the cache might have been modified
+        unpack_archive(&archive_path, name, version, expected_sha256, &target_path)
+    }
+}
+
+/// Returns the SHA-256 digest of a file of the package version if it matches the expected one
+fn verify_checksum(path: &Path, name: &str, version: &str, expected_sha256: &str) -> Result<String, Error> {
+    let sha256 = sha256_file(path)?;
+    if sha256 != expected_sha256 {
+        return Err(anyhow::anyhow!(
+            "Checksum mismatch for package {name}/{version}: expected {expected_sha256}, got {sha256}"
+        ));
+    }
+    Ok(sha256)
+}
+
+/// Unpacks a package tarball and returns its SHA-256 digest. If an expected digest is given the
+/// tarball is verified before unpacking.
+fn unpack_archive(archive_path: &Path, name: &str, version: &str, expected_sha256: Option<&str>, target_path: &Path) -> Result<String, Error> {
+    let sha256 = match expected_sha256 {
+        Some(expected_sha256) => verify_checksum(archive_path, name, version, expected_sha256)?,
+        None => sha256_file(archive_path)?,
+    };
+
+    let archive_file = File::open(archive_path)?;
+    let decoder = GzDecoder::new(archive_file);
+    let mut archive = Archive::new(decoder);
+    archive.unpack(target_path).map_err(|e| anyhow::anyhow!("Failed to unpack archive: {e}"))?;
+    Ok(sha256)
+}
+
+/// Installs a version from a local package directory. The version directory either contains
+/// `manifest.toml` and `package.tar.gz`, whose digest is returned, or the unpacked files.
+fn install_local(path: &str, name: &str, version: &str, expected_sha256: Option<&str>, target_path: &Path) -> Result<Option<String>, Error> {
+    let version_path = local_path(path).join(version);
+    let target_path = target_path.join(name);
+    let archive_path = version_path.join("package.tar.gz");
+    if !archive_path.is_file() {
+        if expected_sha256.is_some() {
+            return Err(anyhow::anyhow!(
+                "Package {name}/{version} was locked as an archive, but {} does not exist",
+                archive_path.display()
+            ));
+        }
+        copy_package(&version_path, &target_path)?;
+        return Ok(None);
+    }
+
+    std::fs::create_dir_all(&target_path)?;
+    std::fs::copy(version_path.join("manifest.toml"), target_path.join("manifest.toml"))?;
+    unpack_archive(&archive_path, name, version, expected_sha256, &target_path).map(Some)
+}
+
+/// Removes an installed package. Links to local packages are removed without touching their target.
+fn remove_package(path: &Path) -> io::Result<()> {
+    match std::fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.is_dir() => std::fs::remove_dir_all(path),
+        Ok(_) => std::fs::remove_file(path).or_else(|_| std::fs::remove_dir(path)),
+        Err(_) => Ok(()),
+    }
+}
+
+#[cfg(unix)]
+fn link_directory(source: &Path, target: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(source, target)
+}
+
+#[cfg(windows)]
+fn link_directory(source: &Path, target: &Path) -> io::Result<()> {
+    std::os::windows::fs::symlink_dir(source, target)
+}
+
+/// Copies a local package, skipping its own `.mb` and `.git` directories
+fn copy_package(source: &Path, target: &Path) -> io::Result<()> {
+    std::fs::create_dir_all(target)?;
+    for entry in std::fs::read_dir(source)? {
+        let entry = entry?;
+        if entry.file_name() == ".mb" || entry.file_name() == ".git" {
+            continue;
+        }
+        if entry.file_type()?.is_dir() {
+            copy_package(&entry.path(), &target.join(entry.file_name()))?;
+        } else {
+            std::fs::copy(entry.path(), target.join(entry.file_name()))?;
+        }
+    }
+    Ok(())
+}
+
+fn is_linked_to(package_path: &Path, source: &Path) -> bool {
+    std::fs::read_link(package_path).is_ok_and(|target| target == source)
+}
+
+/// Dependencies of the root manifest
+#[derive(Default)]
+pub struct ManifestDependencies {
+    /// All dependencies and dev-dependencies as declared in the manifest
+    pub declared: BTreeMap<String, Dependency>,
+    /// Dependencies which are installed
+    pub required: HashMap<String, semver::VersionReq>,
+    /// Optional dependencies and `[constraints]` which only restrict the version if another
+    /// package requires them
+    pub constraints: HashMap<String, semver::VersionReq>,
+    /// Path and git dependencies which are not resolved through the index
+    pub direct: HashMap<String, Dependency>,
+    /// Registries selected for dependencies with a `registry` key
+    pub registries: HashMap<String, String>,
+    /// Replacements of packages in the whole graph from `[patch]`
+    pub patches: BTreeMap<String, Patch>,
+    /// Dependencies for which pre-release versions may be selected
+    pub prereleases: HashSet<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct EntryPatch {
+    package: String,
+    registry: Option<String>,
+}
+
+fn parse_patch(name: &str, value: &toml::Value) -> Result<Patch, Error> {
+    let invalid = |e: String| anyhow::anyhow!("Invalid patch '{name}' in [patch]: {e}");
+    let table = value.as_table().ok_or(invalid("expected a table".to_string()))?;
+    if table.contains_key("package") {
+        let patch = EntryPatch::deserialize(value.clone()).map_err(|e| invalid(e.to_string()))?;
+        return Ok(Patch::Entry { package: patch.package, registry: patch.registry });
     }
+
+    let dependency = Dependency::deserialize(value.clone()).map_err(|e| invalid(e.to_string()))?;
+    if dependency.path.is_none() && dependency.git.is_none() {
+        return Err(invalid("either 'git', 'path' or 'package' must be specified".to_string()));
+    }
+    Ok(Patch::Direct(dependency))
 }
 
-fn clear_or_create_directory(path: &Path) -> io::Result<()> {
-    if path.exists() {
-        std::fs::remove_dir_all(&path)?;
+/// Parses the dependencies of the root manifest. Dev-dependencies are only installed for the
+/// root project, the manifests of packages in the graph are read without them.
+pub fn parse_dependencies(manifest: &toml::Table) -> Result<ManifestDependencies, Error> {
+    let mut dependencies = ManifestDependencies::default();
+    let mut sections: HashMap<String, &str> = HashMap::new();
+    for section in ["dependencies", "dev-dependencies"] {
+        for (name, dependency) in parse_dependency_table(manifest, section)? {
+            if let Some(previous) = sections.insert(name.clone(), section) {
+                return Err(anyhow::anyhow!("Dependency '{name}' is declared in both [{previous}] and [{section}]"));
+            }
+            dependencies.declared.insert(name.clone(), dependency.clone());
+            if let Some(registry) = &dependency.registry {
+                dependencies.registries.insert(name.clone(), registry.clone());
+            }
+            if dependency.pre {
+                dependencies.prereleases.insert(name.clone());
+            }
+            if dependency.path.is_some() || dependency.git.is_some() {
+                dependencies.direct.insert(name.clone(), dependency.clone());
+            }
+            if dependency.optional {
+                dependencies.constraints.insert(name.clone(), dependency.version);
+            } else {
+                dependencies.required.insert(name.clone(), dependency.version);
+            }
+        }
     }
-    std::fs::create_dir_all(&path)
+
+    for (name, req) in parse_constraints(manifest)? {
+        // Both an optional dependency and a constraint restrict the version, so all
+        // comparators have to match
+        dependencies
+            .constraints
+            .entry(name)
+            .and_modify(|existing| existing.comparators.extend(req.comparators.clone()))
+            .or_insert(req);
+    }
+
+    if let Some(patch_table) = manifest.get("patch") {
+        let patch_table = patch_table
+            .as_table()
+            .ok_or(anyhow::anyhow!("[patch] must be a table"))?;
+        for (name, value) in patch_table {
+            if dependencies.direct.contains_key(name) {
+                return Err(anyhow::anyhow!("Dependency '{name}' has a path or git source and cannot be patched"));
+            }
+            dependencies.patches.insert(name.clone(), parse_patch(name, value)?);
+        }
+    }
+
+    Ok(dependencies)
 }
 
-fn parse_dependencies(manifest: &toml::Table) -> Result<HashMap<String, semver::VersionReq>, Error> {
-    let dependency_table = manifest.get("dependencies")
-        .and_then(toml::Value::as_table);
+/// Origin of a package which is not resolved through the index
+enum DirectSource {
+    /// `location` is the path relative to the root manifest, `path` the canonical path which the
+    /// installed package links to
+    Path { location: String, path: PathBuf },
+    Git { source: GitSource, commit: String },
+}
 
-    let mut map = HashMap::new();
-    if let Some(dependency_table) = dependency_table {
-        for (k, v) in dependency_table {
-            map.insert(k.to_string(), semver::VersionReq::parse(v.as_str().unwrap())?);
+impl DirectSource {
+    fn install_request(&self) -> InstallRequest<'_> {
+        match self {
+            DirectSource::Path { location, path } => InstallRequest::Path { location, path: path.clone() },
+            DirectSource::Git { source, commit } => {
+                InstallRequest::Git { url: &source.url, revision: commit.clone(), commit: Some(commit) }
+            },
         }
     }
+}
+
+struct DirectPackage {
+    source: DirectSource,
+    package: Package,
+}
 
-    Ok(map)
+/// Lexically removes `..` components following a directory, e.g. `../foo/../bar` becomes `../bar`
+fn normalize_location(location: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in location.components() {
+        match component {
+            Component::ParentDir if matches!(normalized.components().next_back(), Some(Component::Normal(_))) => {
+                normalized.pop();
+            },
+            Component::CurDir => {},
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// Reads the packages of all path and git dependencies including those declared by direct
+/// packages themselves. Paths are relative to the manifest which declares them.
+fn read_direct_packages(
+    manifest_dir: &Path,
+    dependencies: &HashMap<String, Dependency>,
+    cache: &Cache,
+    offline: bool,
+) -> Result<HashMap<String, DirectPackage>, Error> {
+    // Path dependencies are resolved relative to the location of the declaring local package,
+    // git packages have no location
+    let mut pending = dependencies
+        .iter()
+        .map(|(name, dependency)| (name.clone(), dependency.clone(), Some(PathBuf::new())))
+        .collect_vec();
+    let mut packages: HashMap<String, DirectPackage> = HashMap::new();
+    // Describes where each package was taken from in order to detect conflicting declarations
+    let mut origins: HashMap<String, String> = HashMap::new();
+
+    while let Some((name, dependency, base)) = pending.pop() {
+        let origin = match (&dependency.path, &dependency.git, &base) {
+            (Some(_), _, None) => {
+                return Err(anyhow::anyhow!("Path dependency '{name}' of a git dependency is not supported"));
+            },
+            (Some(path), _, Some(base)) => normalize_location(&base.join(path)).to_string_lossy().replace('\\', "/"),
+            (None, Some(git), _) => format!("{} ({})", git.url, git.reference),
+            (None, None, _) => continue,
+        };
+        if let Some(existing) = origins.get(&name) {
+            if *existing != origin {
+                return Err(anyhow::anyhow!("Dependency '{name}' is declared with different sources '{existing}' and '{origin}'"));
+            }
+            continue;
+        }
+
+        let (source, package, location) = match &dependency.git {
+            Some(git) => {
+                let (package, commit) = read_git_package(&name, git, cache, offline)?;
+                (DirectSource::Git { source: git.clone(), commit }, package, None)
+            },
+            None => {
+                let path = dunce::canonicalize(manifest_dir.join(&origin))
+                    .map_err(|e| anyhow::anyhow!("Path dependency '{name}' at '{origin}' is not accessible: {e}"))?;
+                let package = read_local_package(&name, &path)?;
+                let location = PathBuf::from(&origin);
+                (DirectSource::Path { location: origin.clone(), path }, package, Some(location))
+            },
+        };
+
+        for (dep_name, dep) in &package.dependencies {
+            pending.push((dep_name.clone(), dep.clone(), location.clone()));
+        }
+        origins.insert(name.clone(), origin);
+        packages.insert(name, DirectPackage { source, package });
+    }
+
+    Ok(packages)
+}
+
+fn read_install_record(package_path: &Path) -> Option<LockedPackage> {
+    let content = std::fs::read_to_string(package_path.join(INSTALL_RECORD_FILE)).ok()?;
+    toml::from_str(&content).ok()
+}
+
+fn write_install_record(package_path: &Path, package: &LockedPackage) -> Result<(), Error> {
+    std::fs::write(package_path.join(INSTALL_RECORD_FILE), toml::to_string_pretty(package)?)?;
+    Ok(())
+}
+
+/// Prints a notice about the installed packages, in quiet mode to stderr so that stdout stays machine readable
+fn print_notice(quiet: bool, message: &str) {
+    if quiet {
+        eprintln!("{message}");
+    } else {
+        println!("{message}");
+    }
+}
+
+/// Warns about a yanked or deprecated version of an installed package
+fn print_version_notices(name: &str, version: &semver::Version, metadata: &VersionMetadata, quiet: bool) {
+    if metadata.yanked {
+        print_notice(quiet, &format!("  [!] {name}/{version} has been yanked"));
+    }
+    if let Some(message) = &metadata.deprecated {
+        print_notice(quiet, &format!("  [!] {name}/{version} is deprecated: {message}"));
+    }
+}
+
+/// Removes all installed packages which are not contained in the given set of names
+fn remove_stale_packages(dependencies_path: &Path, keep: &HashSet<&str>, quiet: bool) -> Result<(), Error> {
+    for dir_entry in std::fs::read_dir(dependencies_path)? {
+        let path = dir_entry?.path();
+        let name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+        if keep.contains(name.as_str()) {
+            continue;
+        }
+
+        if !quiet {
+            match read_install_record(&path) {
+                Some(installed) => println!("  [-] {}/{}", installed.name, installed.version),
+                None => println!("  [-] {name}"),
+            }
+        }
+
+        remove_package(&path)?;
+    }
+
+    Ok(())
+}
+
+/// Which versions are preferred when resolving dependencies
+#[derive(Clone, Copy, Default, clap::ValueEnum)]
+pub enum ResolutionStrategy {
+    /// Highest versions
+    #[default]
+    Highest,
+    /// Lowest versions of all packages
+    Lowest,
+    /// Lowest versions of the direct dependencies, highest versions of all other packages
+    LowestDirect,
+}
+
+impl From<ResolutionStrategy> for ResolutionMode {
+    fn from(strategy: ResolutionStrategy) -> Self {
+        match strategy {
+            ResolutionStrategy::Highest => ResolutionMode::Highest,
+            ResolutionStrategy::Lowest => ResolutionMode::Lowest,
+            ResolutionStrategy::LowestDirect => ResolutionMode::LowestDirect,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct InstallOptions {
+    /// Only use locally cached data
+    pub offline: bool,
+    /// Do not print progress messages, e.g. for machine readable output
+    pub quiet: bool,
+    pub resolution: ResolutionStrategy,
+    /// Allow pre-release versions of all packages
+    pub pre: bool,
+}
+
+/// Describes the origin of a package to install. The commit and the digest are only
+/// known for packages which are installed from a lockfile.
+enum InstallRequest<'a> {
+    Git {
+        url: &'a str,
+        revision: String,
+        commit: Option<&'a str>,
+    },
+    Artifactory {
+        location: ArtifactoryLocation<'a>,
+        sha256: Option<&'a str>,
+    },
+    Http {
+        url: &'a str,
+        sha256: Option<&'a str>,
+    },
+    Local {
+        path: &'a str,
+        sha256: Option<&'a str>,
+    },
+    Path {
+        location: &'a str,
+        path: PathBuf,
+    },
+}
+
+impl InstallRequest<'_> {
+    fn kind(&self) -> &'static str {
+        match self {
+            InstallRequest::Git { .. } => "Git",
+            InstallRequest::Artifactory { .. } => "Artifactory",
+            InstallRequest::Http { .. } => "HTTP",
+            InstallRequest::Local { .. } => "local directory",
+            InstallRequest::Path { .. } => "path",
+        }
+    }
+
+    /// Returns true if an installed package fulfills this request
+    fn is_satisfied_by(&self, installed: &LockedSource) -> bool {
+        match (self, installed) {
+            (InstallRequest::Git { url, commit, .. }, LockedSource::Git { url: installed_url, commit: installed_commit }) => {
+                url == installed_url && commit.is_none_or(|c| c == installed_commit)
+            },
+            (
+                InstallRequest::Artifactory { location, sha256 },
+                LockedSource::Artifactory { server, repo, path, sha256: installed_sha256 },
+            ) => {
+                location.server == server && location.repo == repo && location.path == path
+                    && sha256.is_none_or(|s| s == installed_sha256)
+            },
+            (InstallRequest::Http { url, sha256 }, LockedSource::Http { url: installed_url, sha256: installed_sha256 }) => {
+                url == installed_url && sha256.is_none_or(|s| s == installed_sha256)
+            },
+            (InstallRequest::Local { path, sha256 }, LockedSource::Local { path: installed_path, sha256: installed_sha256 }) => {
+                path == installed_path && sha256.is_none_or(|s| installed_sha256.as_deref() == Some(s))
+            },
+            _ => false,
+        }
+    }
+}
+
+struct Installer<'a> {
+    git: GitInstaller<'a>,
+    archive: ArchiveInstaller<'a>,
+    dependencies_path: PathBuf,
+    quiet: bool,
+}
+
+impl<'a> Installer<'a> {
+    fn new(config: &'a ConfigData, cache: &'a Cache, options: &InstallOptions, storage_path: &Path) -> Result<Self, Error> {
+        let dependencies_path = storage_path.join("deps");
+        std::fs::create_dir_all(&dependencies_path)?;
+        let offline = options.offline;
+        Ok(Self {
+            git: GitInstaller { cache, offline },
+            archive: ArchiveInstaller { cache, tokens: &config.artifactory_token, offline },
+            dependencies_path,
+            quiet: options.quiet,
+        })
+    }
+
+    fn is_cached(&self, version: &semver::Version, request: &InstallRequest) -> Result<bool, Error> {
+        match request {
+            InstallRequest::Git { url, revision, .. } => Ok(self.git.is_cached(url, revision)),
+            InstallRequest::Artifactory { location, sha256 } => {
+                Ok(self.archive.is_cached(&location.archive_urls(version.to_string().as_str())?, *sha256))
+            },
+            InstallRequest::Http { url, sha256 } => {
+                Ok(self.archive.is_cached(&ArchiveUrls::http(url, version.to_string().as_str())?, *sha256))
+            },
+            InstallRequest::Local { path, .. } => Ok(local_path(path).join(version.to_string()).is_dir()),
+            InstallRequest::Path { path, .. } => Ok(path.is_dir()),
+        }
+    }
+
+    fn install(&self, name: &str, version: &semver::Version, request: &InstallRequest) -> Result<LockedPackage, Error> {
+        let package_path = self.dependencies_path.join(name);
+        remove_package(&package_path)?;
+
+        if let InstallRequest::Path { location, path } = request {
+            let package = LockedPackage {
+                name: name.to_string(),
+                version: version.clone(),
+                source: LockedSource::Path { path: location.to_string() },
+            };
+            // Copies are refreshed on every install since they cannot pick up edits
+            if let Err(e) = link_directory(path, &package_path) {
+                warn!("Could not link {}, copying it instead: {e}", path.display());
+                copy_package(path, &package_path)?;
+                write_install_record(&package_path, &package)?;
+            }
+            return Ok(package);
+        }
+
+        std::fs::create_dir_all(&package_path)?;
+        let source = match request {
+            InstallRequest::Git { url, revision, .. } => {
+                let commit = self.git.install(url, name, revision, &self.dependencies_path)?;
+                LockedSource::Git { url: url.to_string(), commit }
+            },
+            InstallRequest::Artifactory { location, sha256 } => {
+                let digest = self.archive.install_artifactory(location, name, version.to_string().as_str(), *sha256, &self.dependencies_path)?;
+                LockedSource::Artifactory {
+                    server: location.server.to_string(),
+                    repo: location.repo.to_string(),
+                    path: location.path.to_string(),
+                    sha256: digest,
+                }
+            },
+            InstallRequest::Http { url, sha256 } => {
+                let digest = self.archive.install_http(url, name, version.to_string().as_str(), *sha256, &self.dependencies_path)?;
+                LockedSource::Http { url: url.to_string(), sha256: digest }
+            },
+            InstallRequest::Local { path, sha256 } => {
+                let digest = install_local(path, name, version.to_string().as_str(), *sha256, &self.dependencies_path)?;
+                LockedSource::Local { path: path.to_string(), sha256: digest }
+            },
+            InstallRequest::Path { .. } => unreachable!("local packages are linked above"),
+        };
+
+        let package = LockedPackage { name: name.to_string(), version: version.clone(), source };
+        write_install_record(&package_path, &package)?;
+        Ok(package)
+    }
+
+    /// Brings `.mb/deps` in line with the requested packages and returns the installed packages
+    fn install_all(&self, requests: &[(String, semver::Version, InstallRequest)]) -> Result<Vec<LockedPackage>, Error> {
+        let mut installed = Vec::new();
+        let mut pending = Vec::new();
+        for (name, version, request) in requests {
+            if let InstallRequest::Path { location, path } = request {
+                if is_linked_to(&self.dependencies_path.join(name), path) {
+                    if !self.quiet {
+                        println!("  [=] {name}/{version} (linked)");
+                    }
+                    let source = LockedSource::Path { path: location.to_string() };
+                    installed.push(LockedPackage { name: name.clone(), version: version.clone(), source });
+                    continue;
+                }
+            }
+
+            match read_install_record(&self.dependencies_path.join(name)) {
+                Some(record) if record.version == *version && request.is_satisfied_by(&record.source) => {
+                    if !self.quiet {
+                        println!("  [=] {name}/{version} (up to date)");
+                    }
+                    installed.push(record);
+                },
+                _ => pending.push((name, version, request)),
+            }
+        }
+
+        if self.git.offline {
+            let mut missing = Vec::new();
+            for (name, version, request) in &pending {
+                if !self.is_cached(version, request)? {
+                    missing.push(format!("  - {name}/{version} (from {})", request.kind()));
+                }
+            }
+
+            if !missing.is_empty() {
+                return Err(anyhow::anyhow!(
+                    "The following packages are not available in the package cache:\n{}",
+                    missing.join("\n")
+                ));
+            }
+        }
+
+        // Nothing is removed before it is known that all packages can be installed
+        let keep: HashSet<&str> = requests.iter().map(|(name, _, _)| name.as_str()).collect();
+        remove_stale_packages(&self.dependencies_path, &keep, self.quiet)?;
+
+        for (name, version, request) in pending {
+            if !self.quiet {
+                println!("  [*] {name}/{version} (from {})", request.kind());
+            }
+            installed.push(self.install(name, version, request)?);
+        }
+
+        Ok(installed)
+    }
+}
+
+/// Dependency graph of a manifest after resolving
+pub struct Resolution<'a> {
+    pub dependencies: ManifestDependencies,
+    pub inventory: Inventory<'a>,
+    direct_packages: HashMap<String, DirectPackage>,
+    /// Selected version of every package in the graph
+    pub packages: BTreeMap<String, semver::Version>,
+}
+
+impl Resolution<'_> {
+    /// Describes where a package comes from if it is not taken from the index
+    pub fn direct_source(&self, name: &str) -> Option<String> {
+        self.direct_packages.get(name).map(|p| match &p.source {
+            DirectSource::Path { location, .. } => format!("path {location}"),
+            DirectSource::Git { source, .. } => format!("git {} ({})", source.url, source.reference),
+        })
+    }
+}
+
+/// Inventory prepared with the dependencies of a manifest, ready to be solved
+pub struct Resolver<'a> {
+    dependencies: ManifestDependencies,
+    inventory: Inventory<'a>,
+    direct_packages: HashMap<String, DirectPackage>,
+}
+
+impl<'a> Resolver<'a> {
+    /// Updates the inventory for the manifest located in `manifest_dir`
+    pub fn new(
+        registries: &'a Registries,
+        config: &'a ConfigData,
+        manifest: &toml::Table,
+        manifest_dir: &Path,
+        storage_path: &Path,
+        cache: &'a Cache,
+        options: &InstallOptions,
+    ) -> Result<Self, Error> {
+        let dependencies = parse_dependencies(manifest)?;
+
+        if !options.quiet {
+            println!("Updating cache...");
+        }
+        // Path and git patches replace the package like a direct dependency, but without
+        // requiring it
+        let mut direct = dependencies.direct.clone();
+        for (name, patch) in &dependencies.patches {
+            if let Patch::Direct(dependency) = patch {
+                direct.insert(name.clone(), dependency.clone());
+            }
+        }
+        let direct_packages = read_direct_packages(manifest_dir, &direct, cache, options.offline)?;
+        let inventory_path = storage_path.join("inventory");
+        let mut inventory = Inventory::new(registries, &inventory_path, cache, &config.artifactory_token)?;
+        inventory.set_offline(options.offline);
+        for (name, registry) in &dependencies.registries {
+            inventory.select_registry(name, registry)?;
+        }
+        for (name, patch) in &dependencies.patches {
+            if let Patch::Entry { package, registry } = patch {
+                if let Some(registry) = registry {
+                    inventory.select_registry(package, registry)?;
+                }
+                inventory
+                    .patch_package(name, package)
+                    .map_err(|e| anyhow::anyhow!("Patch for '{name}': {e}"))?;
+            }
+        }
+        inventory.update_cache()?;
+        for (name, req) in &dependencies.constraints {
+            inventory.add_constraint(name, req);
+        }
+        inventory.set_resolution_mode(options.resolution.into(), dependencies.required.keys().cloned().collect());
+        inventory.set_prereleases(options.pre);
+        for name in &dependencies.prereleases {
+            inventory.allow_prereleases(name);
+        }
+        for (name, direct_package) in &direct_packages {
+            inventory.override_package(direct_package.package.clone());
+            // Packages under development often have versions like `1.0.0-dev`, which have to
+            // match the requirements on them
+            inventory.allow_prereleases(name);
+        }
+
+        Ok(Self { dependencies, inventory, direct_packages })
+    }
+
+    pub fn inventory(&self) -> &Inventory<'a> {
+        &self.inventory
+    }
+
+    /// Allows a version which is recorded in the lockfile to be selected even if it was yanked
+    pub fn allow_locked(&mut self, name: &str, version: &semver::Version) {
+        self.inventory.allow_locked(name, version);
+    }
+
+    /// Keeps the given version of a package as long as it satisfies all requirements
+    pub fn favor(&mut self, name: &str, version: &semver::Version) {
+        self.inventory.favor_package(name, version);
+    }
+
+    /// Keeps a package at the given version
+    pub fn pin(&mut self, name: &str, version: &semver::Version) {
+        self.inventory.pin_package(name, version);
+    }
+
+    pub fn solve(self, options: &InstallOptions) -> Result<Resolution<'a>, Error> {
+        if !options.quiet {
+            println!("Resolving dependencies...");
+        }
+        match solve(&self.inventory, self.dependencies.required.clone()) {
+            Ok(result) => Ok(Resolution {
+                dependencies: self.dependencies,
+                inventory: self.inventory,
+                direct_packages: self.direct_packages,
+                packages: result.into_iter().collect(),
+            }),
+            Err(error @ metabuild_resolver::SolverError::Unsolvable(_)) => {
+                let uncached = self.inventory.uncached_packages(&self.dependencies.required);
+                if options.offline && !uncached.is_empty() {
+                    print_notice(
+                        options.quiet,
+                        &format!("No metadata is cached for the following packages: {}", uncached.join(", ")),
+                    );
+                }
+                Err(Error::new(error))
+            },
+            Err(
+                error @ (metabuild_resolver::SolverError::Ambiguous(_) | metabuild_resolver::SolverError::InvalidDependencies(_)),
+            ) => Err(Error::new(error)),
+            Err(metabuild_resolver::SolverError::Cancelled) => {
+                Err(anyhow::anyhow!("Resolving was cancelled"))
+            }
+        }
+    }
+}
+
+/// Installs the resolved packages and writes the lockfile
+fn install_resolution(
+    resolution: &Resolution,
+    config: &ConfigData,
+    lockfile_path: &Path,
+    storage_path: &Path,
+    cache: &Cache,
+    options: &InstallOptions,
+) -> Result<(), Error> {
+    let previous_lockfile = lockfile_path.exists().then(|| Lockfile::read(lockfile_path)).transpose()?;
+
+    if !options.quiet {
+        println!("Installing dependencies...");
+    }
+    let mut requests = Vec::new();
+    for (dep_name, dep_version) in &resolution.packages {
+        if let Some(direct_package) = resolution.direct_packages.get(dep_name) {
+            requests.push((dep_name.clone(), dep_version.clone(), direct_package.source.install_request()));
+            continue;
+        }
+
+        // Digests recorded in an existing lockfile are verified on every install
+        let locked_sha256 = |entry: &Entry| {
+            previous_lockfile
+                .as_ref()
+                .and_then(|l| l.get(dep_name))
+                .filter(|p| p.version == *dep_version && p.source.matches_entry(entry))
+                .and_then(|p| p.source.sha256())
+        };
+        let request = match resolution.inventory.get_entry(dep_name)? {
+            Entry::Git { url } => InstallRequest::Git { url, revision: dep_version.to_string(), commit: None },
+            dep_entry @ Entry::Artifactory { server, repo, path } => {
+                InstallRequest::Artifactory { location: ArtifactoryLocation { server, repo, path }, sha256: locked_sha256(dep_entry) }
+            },
+            dep_entry @ Entry::Http { url, .. } => InstallRequest::Http { url, sha256: locked_sha256(dep_entry) },
+            dep_entry @ Entry::Local { path } => InstallRequest::Local { path, sha256: locked_sha256(dep_entry) },
+        };
+        requests.push((dep_name.clone(), dep_version.clone(), request));
+    }
+
+    let installer = Installer::new(config, cache, options, storage_path)?;
+    let locked_packages = installer.install_all(&requests)?;
+    for (dep_name, dep_version) in &resolution.packages {
+        let Some(metadata) = resolution.inventory.get_version_metadata(dep_name, dep_version) else {
+            continue;
+        };
+        if resolution.direct_packages.contains_key(dep_name) {
+            continue;
+        }
+        print_version_notices(dep_name, dep_version, metadata, options.quiet);
+    }
+    for (name, patch) in &resolution.dependencies.patches {
+        let notice = match resolution.packages.get(name) {
+            Some(version) => format!("  [!] {name}/{version} is patched with {patch}"),
+            None => format!("  [!] Patch for {name} is not used"),
+        };
+        print_notice(options.quiet, &notice);
+    }
+    let dependencies = &resolution.dependencies;
+    Lockfile::new(&dependencies.declared, &dependencies.patches, locked_packages).write(lockfile_path)
 }
 
 pub fn install_dependencies(
-    index: &Index,
+    registries: &Registries,
     config: &ConfigData,
     manifest: &toml::Table,
+    lockfile_path: &Path,
     storage_path: &Path,
+    cache: &Cache,
+    options: &InstallOptions,
 ) -> Result<(), Error> {
-    // Clear module installation path if it exists or create it
-    let dependencies_path = storage_path.join("deps");
-    clear_or_create_directory(dependencies_path.as_path())?;
-
-    let dependencies: HashMap<String, semver::VersionReq> = parse_dependencies(manifest)?;
-
-    println!("Updating cache...");
-    let inventory_path = storage_path.join("inventory");
-    let mut inventory = Inventory::new(&index, &inventory_path, &config.artifactory_token)?;
-    inventory.update_cache()?;
-
-    println!("Resolving dependencies...");
-    match solve(&inventory, dependencies) {
-        Ok(result) => {
-            println!("Installing dependencies...");
-            let git_installer = GitInstaller {};
-            let artifactory_installer: ArtifactoryInstaller = ArtifactoryInstaller {};
-            for (dep_name, dep_version) in result {
-                let dep_entry = inventory.index().get_entry(&dep_name)?;
-                let source = match dep_entry {
-                    Entry::Git { .. } => "Git",
-                    Entry::Artifactory {..} => "Artifactory",
-                };
-
-                println!("  [*] {dep_name}/{dep_version} (from {source})");
-
-                match dep_entry {
-                    Entry::Git { url } => {
-                        git_installer.install(url, dep_name.as_str(), dep_version.to_string().as_str(), dependencies_path.as_path())?;
-                    },
-                    Entry::Artifactory { server, repo, path } => {
-                        artifactory_installer.install(server, repo, path, dep_name.as_str(), dep_version.to_string().as_str(), dependencies_path.as_path())?;
+    // The lockfile is located next to the manifest, path dependencies are relative to it
+    let manifest_dir = lockfile_path.parent().unwrap_or(Path::new("."));
+    let mut resolver = Resolver::new(registries, config, manifest, manifest_dir, storage_path, cache, options)?;
+    // Locked versions are kept so that installs are reproducible, only packages whose
+    // requirements changed are resolved again
+    if lockfile_path.exists() {
+        for package in Lockfile::read(lockfile_path)?.packages {
+            resolver.allow_locked(&package.name, &package.version);
+            resolver.favor(&package.name, &package.version);
+        }
+    }
+    let resolution = resolver.solve(options)?;
+    install_resolution(&resolution, config, lockfile_path, storage_path, cache, options)
+}
+
+/// Returns the currently installed versions, taken from the lockfile if there is one and from
+/// the install records in `.mb/deps` otherwise
+fn installed_packages(lockfile_path: &Path, storage_path: &Path) -> Result<BTreeMap<String, semver::Version>, Error> {
+    if lockfile_path.exists() {
+        let lockfile = Lockfile::read(lockfile_path)?;
+        return Ok(lockfile.packages.into_iter().map(|p| (p.name, p.version)).collect());
+    }
+
+    let Ok(entries) = std::fs::read_dir(storage_path.join("deps")) else {
+        return Ok(BTreeMap::new());
+    };
+
+    Ok(entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| read_install_record(&entry.path()))
+        .map(|record| (record.name, record.version))
+        .collect())
+}
+
+/// Resolves the graph of the installed packages. Their versions are kept, even if they were
+/// yanked, as long as they satisfy the manifest. Packages which are not installed yet or
+/// whose requirements changed are resolved like `mb install` would.
+pub fn resolve_installed<'a>(
+    mut resolver: Resolver<'a>,
+    lockfile_path: &Path,
+    storage_path: &Path,
+    options: &InstallOptions,
+) -> Result<Resolution<'a>, Error> {
+    for (name, version) in installed_packages(lockfile_path, storage_path)? {
+        resolver.allow_locked(&name, &version);
+        resolver.favor(&name, &version);
+    }
+    resolver.solve(options)
+}
+
+fn print_version_changes(before: &BTreeMap<String, semver::Version>, after: &BTreeMap<String, semver::Version>) {
+    let names: BTreeSet<&String> = before.keys().chain(after.keys()).collect();
+    let mut changed = false;
+    for name in names {
+        match (before.get(name), after.get(name)) {
+            (Some(old), Some(new)) if old != new => println!("  {name} {old} -> {new}"),
+            (Some(_), Some(_)) => continue,
+            (Some(old), None) => println!("  - {name} {old}"),
+            (None, Some(new)) => println!("  + {name} {new}"),
+            (None, None) => continue,
+        }
+        changed = true;
+    }
+    if !changed {
+        println!("  No versions changed");
+    }
+}
+
+/// Packages selected for `mb update`
+pub struct UpdateOptions<'a> {
+    pub packages: &'a [String],
+    /// Also update the dependencies of the given packages
+    pub recursive: bool,
+}
+
+/// Updates the given packages while all other installed packages keep their versions
+pub fn update_dependencies(
+    mut resolver: Resolver,
+    config: &ConfigData,
+    lockfile_path: &Path,
+    storage_path: &Path,
+    cache: &Cache,
+    update: &UpdateOptions,
+    options: &InstallOptions,
+) -> Result<(), Error> {
+    let packages = update.packages;
+    let installed = installed_packages(lockfile_path, storage_path)?;
+
+    for name in packages {
+        if !installed.contains_key(name) && !resolver.dependencies.required.contains_key(name) {
+            return Err(anyhow::anyhow!("Package '{name}' is neither installed nor a dependency in the manifest"));
+        }
+    }
+
+    let mut free: HashSet<&str> = packages.iter().map(String::as_str).collect();
+    if update.recursive {
+        let mut pending = packages.iter().map(String::as_str).collect_vec();
+        while let Some(name) = pending.pop() {
+            let Some(package) = installed.get(name).and_then(|v| resolver.inventory().get_package(name, v).ok()) else {
+                continue;
+            };
+            for dep_name in package.dependencies.keys() {
+                if let Some((dep_name, _)) = installed.get_key_value(dep_name) {
+                    if free.insert(dep_name) {
+                        pending.push(dep_name);
                     }
                 }
             }
+        }
+    }
+
+    for (name, version) in &installed {
+        resolver.allow_locked(name, version);
+        if !free.contains(name.as_str()) {
+            resolver.pin(name, version);
+        }
+    }
+
+    let resolution = resolver.solve(options)?;
+    if !options.quiet {
+        println!("Updating dependencies...");
+        print_version_changes(&installed, &resolution.packages);
+    }
+    install_resolution(&resolution, config, lockfile_path, storage_path, cache, options)
+}
+
+/// Returns the index metadata of a locked package, path and git packages have none
+fn locked_version_metadata<'a>(
+    registries: &'a Registries,
+    dependencies: &ManifestDependencies,
+    package: &LockedPackage,
+) -> Option<&'a VersionMetadata> {
+    if dependencies.direct.contains_key(&package.name) {
+        return None;
+    }
+    let (entry_name, registry) = match dependencies.patches.get(&package.name) {
+        Some(Patch::Direct(_)) => return None,
+        Some(Patch::Entry { package, registry }) => (package.as_str(), registry.as_deref()),
+        None => (package.name.as_str(), dependencies.registries.get(&package.name).map(String::as_str)),
+    };
+    let names = match registry {
+        Some(registry) => vec![registry],
+        None => registries.find(entry_name),
+    };
+    names
+        .into_iter()
+        .filter_map(|name| registries.get(name))
+        .find_map(|index| index.get_version_metadata(entry_name, &package.version))
+}
+
+/// Installs the packages from the lockfile. Yanked and deprecated versions are reported if the
+/// `registries` of the manifest could be opened.
+pub fn install_locked_dependencies(
+    registries: Option<&Registries>,
+    config: &ConfigData,
+    manifest: &toml::Table,
+    lockfile_path: &Path,
+    storage_path: &Path,
+    cache: &Cache,
+    options: &InstallOptions,
+) -> Result<(), Error> {
+    let dependencies = parse_dependencies(manifest)?;
+    let lockfile = Lockfile::read(lockfile_path)?;
+    lockfile.verify(&dependencies.declared, &dependencies.patches, options.pre)?;
+
+    if !options.quiet {
+        println!("Installing locked dependencies...");
+    }
+    let manifest_dir = lockfile_path.parent().unwrap_or(Path::new("."));
+    let requests = lockfile
+        .packages
+        .iter()
+        .map(|package| {
+            let request = match &package.source {
+                LockedSource::Git { url, commit } => {
+                    InstallRequest::Git { url, revision: commit.clone(), commit: Some(commit) }
+                },
+                LockedSource::Artifactory { server, repo, path, sha256 } => {
+                    InstallRequest::Artifactory { location: ArtifactoryLocation { server, repo, path }, sha256: Some(sha256) }
+                },
+                LockedSource::Http { url, sha256 } => InstallRequest::Http { url, sha256: Some(sha256) },
+                LockedSource::Local { path, sha256 } => InstallRequest::Local { path, sha256: sha256.as_deref() },
+                LockedSource::Path { path } => {
+                    let canonical_path = dunce::canonicalize(manifest_dir.join(path))
+                        .map_err(|e| anyhow::anyhow!("Path dependency '{}' at '{path}' is not accessible: {e}", package.name))?;
+                    InstallRequest::Path { location: path, path: canonical_path }
+                },
+            };
+            Ok((package.name.clone(), package.version.clone(), request))
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    let installer = Installer::new(config, cache, options, storage_path)?;
+    installer.install_all(&requests)?;
+    if let Some(registries) = registries {
+        for package in &lockfile.packages {
+            if let Some(metadata) = locked_version_metadata(registries, &dependencies, package) {
+                print_version_notices(&package.name, &package.version, metadata, options.quiet);
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use metabuild_resolver::cache::sha256_digest;
+    use metabuild_resolver::index::Index;
+
+    /// Adds a version without dependencies to a local package directory
+    fn add_local_version(packages_path: &Path, name: &str, version: &str) {
+        let version_path = packages_path.join(name).join(version);
+        std::fs::create_dir_all(&version_path).unwrap();
+        std::fs::write(version_path.join("manifest.toml"), format!("[package]\nname = \"{name}\"\nversion = \"{version}\"\n")).unwrap();
+    }
+
+    /// Creates an index repository with a local entry for each of the given packages
+    fn local_index(temp_dir: &Path, names: &[&str]) -> Index {
+        let entries: serde_json::Map<String, serde_json::Value> = names
+            .iter()
+            .map(|name| {
+                let path = temp_dir.join("packages").join(name).to_string_lossy().to_string();
+                (name.to_string(), serde_json::json!({ "type": "local", "path": path }))
+            })
+            .collect();
+
+        let source_path = temp_dir.join("index-source");
+        let repo = git2::Repository::init_opts(&source_path, git2::RepositoryInitOptions::new().initial_head("main")).unwrap();
+        std::fs::write(source_path.join("index.json"), serde_json::to_string_pretty(&entries).unwrap()).unwrap();
+        let mut repo_index = repo.index().unwrap();
+        repo_index.add_path(Path::new("index.json")).unwrap();
+        let tree = repo.find_tree(repo_index.write_tree().unwrap()).unwrap();
+        let signature = git2::Signature::now("metabuild", "metabuild@example.com").unwrap();
+        repo.commit(Some("HEAD"), &signature, &signature, "Add packages", &tree, &[]).unwrap();
+
+        Index::new(&source_path.to_string_lossy(), "main", &temp_dir.join("index")).unwrap()
+    }
+
+    /// Project with the local packages `a` and `b` at version 1.0.0, both required as `^1`
+    struct Project {
+        temp_dir: tempfile::TempDir,
+        registries: Registries,
+        cache: Cache,
+        config: ConfigData,
+        manifest: toml::Table,
+    }
+
+    impl Project {
+        fn new() -> Self {
+            let temp_dir = tempfile::tempdir().unwrap();
+            add_local_version(&temp_dir.path().join("packages"), "a", "1.0.0");
+            add_local_version(&temp_dir.path().join("packages"), "b", "1.0.0");
+            let registries = Registries::from(local_index(temp_dir.path(), &["a", "b"]));
+            let cache = Cache::new(&temp_dir.path().join("cache")).unwrap();
+            let manifest = toml::from_str("[dependencies]\na = \"^1\"\nb = \"^1\"\n").unwrap();
+            Self { temp_dir, registries, cache, config: ConfigData::default(), manifest }
+        }
 
-            Ok(())
-        },
-        Err(metabuild_resolver::SolverError::Unsolvable(reason)) => {
-            println!("{}", reason);
-            Err(anyhow::anyhow!("Could not resolve dependencies"))
-        },
-        Err(metabuild_resolver::SolverError::Cancelled) => {
-            Err(anyhow::anyhow!("Resolving was cancelled"))
+        fn lockfile_path(&self) -> PathBuf {
+            self.temp_dir.path().join("manifest.lock")
         }
+
+        fn storage_path(&self) -> PathBuf {
+            self.temp_dir.path().join(".mb")
+        }
+
+        fn install(&self) {
+            let options = InstallOptions { quiet: true, ..Default::default() };
+            let storage_path = self.storage_path();
+            install_dependencies(&self.registries, &self.config, &self.manifest, &self.lockfile_path(), &storage_path, &self.cache, &options)
+                .unwrap();
+        }
+
+        fn update(&self, packages: &[String]) {
+            let options = InstallOptions { quiet: true, ..Default::default() };
+            let storage_path = self.storage_path();
+            let resolver = Resolver::new(&self.registries, &self.config, &self.manifest, self.temp_dir.path(), &storage_path, &self.cache, &options)
+                .unwrap();
+            let update = UpdateOptions { packages, recursive: false };
+            update_dependencies(resolver, &self.config, &self.lockfile_path(), &storage_path, &self.cache, &update, &options).unwrap();
+        }
+
+        fn locked_version(&self, name: &str) -> String {
+            Lockfile::read(&self.lockfile_path()).unwrap().get(name).unwrap().version.to_string()
+        }
+    }
+
+    #[test]
+    fn test_install_keeps_locked_versions() {
+        let project = Project::new();
+        project.install();
+        assert_eq!(project.locked_version("a"), "1.0.0");
+
+        add_local_version(&project.temp_dir.path().join("packages"), "a", "1.1.0");
+        project.install();
+        assert_eq!(project.locked_version("a"), "1.0.0");
+
+        // A requirement which excludes the locked version resolves the package again
+        let mut project = project;
+        project.manifest = toml::from_str("[dependencies]\na = \"^1.1\"\nb = \"^1\"\n").unwrap();
+        project.install();
+        assert_eq!(project.locked_version("a"), "1.1.0");
+        assert_eq!(project.locked_version("b"), "1.0.0");
+    }
+
+    #[test]
+    fn test_checksum_mismatch() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive_path = dir.path().join("package.tar.gz");
+        std::fs::write(&archive_path, b"not the expected content").unwrap();
+        let actual = sha256_digest(b"not the expected content");
+        let expected = sha256_digest(b"expected content");
+
+        assert_eq!(verify_checksum(&archive_path, "a", "1.0.0", &actual).unwrap(), actual);
+        let error = verify_checksum(&archive_path, "a", "1.0.0", &expected).unwrap_err().to_string();
+        assert!(error.contains("a/1.0.0"), "{error}");
+        assert!(error.contains(&format!("expected {expected}")), "{error}");
+        assert!(error.contains(&format!("got {actual}")), "{error}");
+
+        // A tarball with a different digest than locked is not unpacked
+        let target_path = dir.path().join("a");
+        let result = unpack_archive(&archive_path, "a", "1.0.0", Some(&expected), &target_path);
+        assert!(result.unwrap_err().to_string().contains("Checksum mismatch"));
+        assert!(!target_path.exists());
+    }
+
+    #[test]
+    fn test_update_keeps_other_packages() {
+        let project = Project::new();
+        project.install();
+        add_local_version(&project.temp_dir.path().join("packages"), "a", "1.1.0");
+        add_local_version(&project.temp_dir.path().join("packages"), "b", "1.1.0");
+
+        project.update(&["a".to_string()]);
+        assert_eq!(project.locked_version("a"), "1.1.0");
+        assert_eq!(project.locked_version("b"), "1.0.0");
+
+        project.install();
+        assert_eq!(project.locked_version("a"), "1.1.0");
+        assert_eq!(project.locked_version("b"), "1.0.0");
     }
 }
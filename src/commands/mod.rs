@@ -1,4 +1,9 @@
+pub mod cache;
 pub mod config;
 pub mod index;
 pub mod install;
+pub mod outdated;
+pub mod publish;
+pub mod tree;
 pub mod update;
+pub mod why;
@@ -0,0 +1,105 @@
+use crate::commands::install::Resolution;
+use crate::lockfile::Lockfile;
+use anyhow::Error;
+use serde::Serialize;
+use std::collections::BTreeSet;
+
+#[derive(Clone, Copy, Default, clap::ValueEnum)]
+pub enum OutputFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+#[derive(Serialize)]
+struct OutdatedPackage<'a> {
+    name: &'a str,
+    /// Version recorded in the lockfile by the last install
+    installed: Option<&'a semver::Version>,
+    /// Version which `mb install` would select with the current manifest
+    compatible: Option<&'a semver::Version>,
+    latest: Option<&'a semver::Version>,
+    direct: bool,
+}
+
+impl OutdatedPackage<'_> {
+    fn is_outdated(&self) -> bool {
+        match (self.installed.or(self.compatible), self.latest) {
+            (Some(current), Some(latest)) => current < latest,
+            _ => false,
+        }
+    }
+}
+
+fn format_version(version: Option<&semver::Version>) -> String {
+    version.map_or("-".to_string(), semver::Version::to_string)
+}
+
+fn print_table(packages: &[OutdatedPackage]) {
+    if packages.is_empty() {
+        println!("All dependencies are up to date");
+        return;
+    }
+
+    let rows: Vec<[String; 5]> = packages
+        .iter()
+        .map(|p| {
+            [
+                p.name.to_string(),
+                format_version(p.installed),
+                format_version(p.compatible),
+                format_version(p.latest),
+                if p.direct { "direct" } else { "transitive" }.to_string(),
+            ]
+        })
+        .collect();
+    let header = ["Name", "Installed", "Compatible", "Latest", "Kind"].map(String::from);
+    let widths: Vec<usize> = (0..header.len())
+        .map(|i| rows.iter().chain([&header]).map(|row| row[i].len()).max().unwrap_or(0))
+        .collect();
+    for row in [&header].into_iter().chain(&rows) {
+        let line: Vec<String> = row.iter().zip(&widths).map(|(cell, width)| format!("{cell:width$}")).collect();
+        println!("{}", line.join("  ").trim_end());
+    }
+}
+
+/// Returns the newest version of a package which may be selected, yanked versions and
+/// pre-releases which are not allowed are skipped
+fn latest_version<'a>(resolution: &'a Resolution, name: &str) -> Option<&'a semver::Version> {
+    let inventory = &resolution.inventory;
+    inventory.get_versions(name).into_iter().rfind(|version| {
+        let yanked = inventory.get_version_metadata(name, version).is_some_and(|metadata| metadata.yanked);
+        !yanked && (version.pre.is_empty() || inventory.allows_prereleases(name))
+    })
+}
+
+/// Reports all dependencies for which the index offers a newer version and returns whether
+/// any were found
+pub fn outdated(resolution: &Resolution, lockfile: Option<&Lockfile>, format: OutputFormat) -> Result<bool, Error> {
+    let names: BTreeSet<&str> = resolution
+        .packages
+        .keys()
+        .map(String::as_str)
+        .chain(lockfile.iter().flat_map(|l| l.packages.iter().map(|p| p.name.as_str())))
+        .collect();
+
+    let packages: Vec<OutdatedPackage> = names
+        .into_iter()
+        // Path and git dependencies have no other versions to compare with
+        .filter(|name| resolution.direct_source(name).is_none())
+        .map(|name| OutdatedPackage {
+            name,
+            installed: lockfile.and_then(|l| l.get(name)).map(|p| &p.version),
+            compatible: resolution.packages.get(name),
+            latest: latest_version(resolution, name),
+            direct: resolution.dependencies.required.contains_key(name),
+        })
+        .filter(OutdatedPackage::is_outdated)
+        .collect();
+
+    match format {
+        OutputFormat::Text => print_table(&packages),
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&packages)?),
+    }
+    Ok(!packages.is_empty())
+}
@@ -0,0 +1,350 @@
+use crate::commands::config::ConfigData;
+use crate::commands::install::parse_dependencies;
+use anyhow::{anyhow, Error};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use git2::{Repository, StatusOptions};
+use glob::Pattern;
+use log::debug;
+use metabuild_resolver::cache::sha256_file;
+use metabuild_resolver::index::{http_version_url, local_path, Entry, Index};
+use metabuild_git::{make_git_authenticator, make_git_config, make_push_options};
+use metabuild_resolver::inventory::{find_artifactory_token, ArtifactoryMetadataRetriever, MetadataRetriever};
+use metabuild_resolver::manifest::parse_dependencies as parse_dependency_table;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tempfile::TempDir;
+
+/// Directories which are never part of a package
+const ALWAYS_EXCLUDED: [&str; 2] = [".git", ".mb"];
+
+/// Number of attempts to find the published version via AQL, the search index is updated asynchronously
+const VERIFY_ATTEMPTS: u32 = 5;
+
+struct PackageInfo {
+    name: String,
+    version: semver::Version,
+    include: Vec<Pattern>,
+    exclude: Vec<Pattern>,
+}
+
+fn parse_patterns(package: &toml::Table, key: &str, default: &[&str]) -> Result<Vec<Pattern>, Error> {
+    let patterns = match package.get(key) {
+        Some(toml::Value::Array(values)) => values
+            .iter()
+            .map(|v| v.as_str().map(String::from).ok_or(anyhow!("Entries of 'package.{key}' must be strings")))
+            .collect::<Result<Vec<_>, _>>()?,
+        Some(_) => return Err(anyhow!("'package.{key}' must be an array of glob patterns")),
+        None => default.iter().map(|p| p.to_string()).collect(),
+    };
+
+    patterns
+        .iter()
+        .map(|p| Pattern::new(p).map_err(|e| anyhow!("Invalid pattern '{p}' in 'package.{key}': {e}")))
+        .collect()
+}
+
+fn parse_package_info(manifest: &toml::Table) -> Result<PackageInfo, Error> {
+    let package = manifest
+        .get("package")
+        .and_then(toml::Value::as_table)
+        .ok_or(anyhow!("The manifest has no [package] section"))?;
+    let name = package
+        .get("name")
+        .and_then(toml::Value::as_str)
+        .ok_or(anyhow!("'package.name' is missing in the manifest"))?;
+    let version = package
+        .get("version")
+        .and_then(toml::Value::as_str)
+        .ok_or(anyhow!("'package.version' is missing in the manifest"))?;
+    let version = semver::Version::parse(version)
+        .map_err(|e| anyhow!("Invalid 'package.version' '{version}': {e}"))?;
+
+    Ok(PackageInfo {
+        name: name.to_string(),
+        version,
+        include: parse_patterns(package, "include", &["**/*"])?,
+        exclude: parse_patterns(package, "exclude", &[])?,
+    })
+}
+
+/// Recursively collects all files below `dir` which match the include and exclude rules,
+/// returned relative to `root`
+fn collect_files(root: &Path, dir: &Path, package: &PackageInfo, files: &mut Vec<PathBuf>) -> Result<(), Error> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let relative_path = path.strip_prefix(root)?.to_path_buf();
+        if path.is_dir() {
+            if dir == root && ALWAYS_EXCLUDED.iter().any(|e| path.ends_with(e)) {
+                continue;
+            }
+            collect_files(root, &path, package, files)?;
+        } else if package.include.iter().any(|p| p.matches_path(&relative_path))
+            && !package.exclude.iter().any(|p| p.matches_path(&relative_path))
+        {
+            files.push(relative_path);
+        }
+    }
+
+    Ok(())
+}
+
+/// Builds the package tarball, the published manifest is always stored as `manifest.toml`
+fn build_tarball(root: &Path, manifest_path: &Path, package: &PackageInfo, target: &Path) -> Result<usize, Error> {
+    let mut files = Vec::new();
+    collect_files(root, root, package, &mut files)?;
+    let manifest_name = manifest_path.file_name().map(PathBuf::from);
+    files.retain(|file| file != Path::new("manifest.toml") && Some(file) != manifest_name.as_ref());
+    files.sort();
+
+    let encoder = GzEncoder::new(File::create(target)?, Compression::default());
+    let mut builder = tar::Builder::new(encoder);
+    for file in &files {
+        debug!("Adding {:?} to package", file);
+        builder.append_path_with_name(root.join(file), file)?;
+    }
+    builder.append_path_with_name(manifest_path, "manifest.toml")?;
+    builder.into_inner()?.finish()?;
+    Ok(files.len() + 1)
+}
+
+/// Dependencies of a published package are resolved through the indexes of its consumers, which
+/// do not know the registries, directories and repositories of this project
+fn check_published_dependencies(manifest: &toml::Table) -> Result<(), Error> {
+    for (name, dependency) in parse_dependency_table(manifest, "dependencies")? {
+        if let Some(key) = dependency.source_key() {
+            return Err(anyhow!(
+                "Dependency '{name}' sets '{key}', which is not supported in published packages. \
+                 Publish '{name}' to the index and depend on a version instead."
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn make_request(method: &str, url: &str, token: Option<&str>) -> ureq::Request {
+    let mut request = ureq::request(method, url);
+    if let Some(token) = token {
+        request = request.set("Authorization", format!("Bearer {token}").as_str());
+    }
+    request
+}
+
+fn upload_file(url: &str, file: &Path, token: Option<&str>) -> Result<(), Error> {
+    let sha256 = sha256_file(file)?;
+    let request = make_request("PUT", url, token).set("X-Checksum-Sha256", &sha256);
+    match request.send(File::open(file)?) {
+        Ok(_) => Ok(()),
+        Err(ureq::Error::Status(code, response)) => {
+            Err(anyhow!("Upload to {url} failed with code {code}: {}", response.into_string()?))
+        },
+        Err(e) => Err(anyhow!("Upload to {url} failed: {e}")),
+    }
+}
+
+fn version_exists(url: &str, token: Option<&str>) -> Result<bool, Error> {
+    match make_request("HEAD", url, token).call() {
+        Ok(_) => Ok(true),
+        Err(ureq::Error::Status(404, _)) => Ok(false),
+        Err(ureq::Error::Status(code, response)) => {
+            Err(anyhow!("Server returned code {code} for {url}: {}", response.status_text()))
+        },
+        Err(e) => Err(anyhow!("Could not query {url}: {e}")),
+    }
+}
+
+fn publish_artifactory(
+    config: &ConfigData,
+    package_root: &Path,
+    manifest_path: &Path,
+    package: &PackageInfo,
+    (server, repo, path): (&str, &str, &str),
+    force: bool,
+) -> Result<(), Error> {
+    let token = find_artifactory_token(&config.artifactory_token, server);
+    let base_url = format!("{server}/{repo}/{path}/{}", package.version);
+
+    if version_exists(&format!("{base_url}/manifest.toml"), token)? {
+        if !force {
+            return Err(anyhow!(
+                "Version {} of '{}' already exists in {server}/{repo}/{path}. Use --force to overwrite it.",
+                package.version,
+                package.name
+            ));
+        }
+        println!("Overwriting existing version {}", package.version);
+    }
+
+    let temp_dir = TempDir::new()?;
+    let tarball = temp_dir.path().join("package.tar.gz");
+    let file_count = build_tarball(package_root, manifest_path, package, &tarball)?;
+    println!("Packaged {file_count} files");
+
+    // Upload the manifest last, it is what makes the version visible to the resolver
+    println!("Uploading to {base_url}...");
+    upload_file(&format!("{base_url}/package.tar.gz"), &tarball, token)?;
+    upload_file(&format!("{base_url}/manifest.toml"), manifest_path, token)?;
+
+    let retriever = ArtifactoryMetadataRetriever::new(server, repo, path, token);
+    let version = package.version.to_string();
+    for attempt in 1..=VERIFY_ATTEMPTS {
+        if retriever.fetch_versions()?.contains(&version) {
+            println!("Published {}/{}", package.name, package.version);
+            return Ok(());
+        }
+        debug!("Version {version} not yet found via AQL (attempt {attempt})");
+        std::thread::sleep(Duration::from_secs(2));
+    }
+
+    Err(anyhow!(
+        "Version {} of '{}' was uploaded but is not found by the Artifactory search",
+        package.version,
+        package.name
+    ))
+}
+
+fn publish_local(package_root: &Path, manifest_path: &Path, package: &PackageInfo, path: &str, force: bool) -> Result<(), Error> {
+    let package_path = local_path(path);
+    let version_path = package_path.join(package.version.to_string());
+    if version_path.exists() {
+        if !force {
+            return Err(anyhow!(
+                "Version {} of '{}' already exists in {}. Use --force to overwrite it.",
+                package.version,
+                package.name,
+                package_path.display()
+            ));
+        }
+        println!("Overwriting existing version {}", package.version);
+    }
+
+    // The version becomes visible to the resolver with the rename, the temporary name is not a version
+    let temp_path = package_path.join(format!(".{}.tmp", package.version));
+    if temp_path.exists() {
+        std::fs::remove_dir_all(&temp_path)?;
+    }
+    std::fs::create_dir_all(&temp_path)?;
+    let file_count = build_tarball(package_root, manifest_path, package, &temp_path.join("package.tar.gz"))?;
+    println!("Packaged {file_count} files");
+    std::fs::copy(manifest_path, temp_path.join("manifest.toml"))?;
+
+    if version_path.exists() {
+        std::fs::remove_dir_all(&version_path)?;
+    }
+    std::fs::rename(&temp_path, &version_path)?;
+    println!("Published {}/{} to {}", package.name, package.version, version_path.display());
+    Ok(())
+}
+
+fn check_clean_working_tree(repo: &Repository) -> Result<(), Error> {
+    let mut options = StatusOptions::new();
+    options.include_untracked(true).include_ignored(false);
+    let statuses = repo.statuses(Some(&mut options))?;
+    if statuses.is_empty() {
+        return Ok(());
+    }
+
+    let files = statuses
+        .iter()
+        .filter_map(|s| s.path().map(|p| format!("  - {p}")))
+        .collect::<Vec<_>>()
+        .join("\n");
+    Err(anyhow!("The working tree has uncommitted changes:\n{files}\nCommit or stash them before publishing."))
+}
+
+fn publish_git(package_root: &Path, package: &PackageInfo, url: &str, force: bool) -> Result<(), Error> {
+    if force {
+        return Err(anyhow!("--force is not supported for Git packages, published tags are never moved"));
+    }
+
+    let repo = Repository::discover(package_root)
+        .map_err(|e| anyhow!("Could not open Git repository in {}: {e}", package_root.display()))?;
+    check_clean_working_tree(&repo)?;
+
+    let remote_name = repo
+        .remotes()?
+        .iter()
+        .flatten()
+        .find(|name| repo.find_remote(name).is_ok_and(|r| r.url() == Some(url)))
+        .map(String::from)
+        .ok_or(anyhow!("The repository has no remote with the package url {url}"))?;
+
+    let tag_name = package.version.to_string();
+    if repo.revparse_single(&format!("refs/tags/{tag_name}")).is_ok() {
+        return Err(anyhow!(
+            "Tag {tag_name} already exists. Bump 'package.version' in the manifest before publishing."
+        ));
+    }
+
+    let head = repo.head()?.peel_to_commit()?;
+    repo.tag_lightweight(&tag_name, head.as_object(), false)?;
+    debug!("Created tag {tag_name} on {}", head.id());
+
+    println!("Pushing tag {tag_name} to {remote_name}...");
+    let mut remote = repo.find_remote(&remote_name)?;
+    let git_config = make_git_config()?;
+    let auth = make_git_authenticator();
+    let mut push_options = make_push_options(&auth, &git_config);
+    let reference = format!("refs/tags/{tag_name}");
+    if let Err(e) = remote.push(&[&reference], Some(&mut push_options)) {
+        // Do not leave a local tag behind which was never published
+        repo.tag_delete(&tag_name)?;
+        return Err(anyhow!("Could not push tag {tag_name}: {e}"));
+    }
+
+    println!("Published {}/{}", package.name, package.version);
+    Ok(())
+}
+
+pub fn publish(
+    index: &Index,
+    config: &ConfigData,
+    manifest: &toml::Table,
+    manifest_path: &Path,
+    force: bool,
+) -> Result<(), Error> {
+    let package = parse_package_info(manifest)?;
+    let package_root = match manifest_path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+
+    // A tag with a broken manifest would break dependency resolution for every consumer
+    parse_dependencies(manifest)?;
+    check_published_dependencies(manifest)?;
+
+    println!("Publishing {}/{}...", package.name, package.version);
+    match index.get_entry(&package.name)? {
+        Entry::Artifactory { server, repo, path } => {
+            publish_artifactory(config, &package_root, manifest_path, &package, (server, repo, path), force)
+        },
+        Entry::Git { url } => publish_git(&package_root, &package, url, force),
+        Entry::Local { path } => publish_local(&package_root, manifest_path, &package, path, force),
+        Entry::Http { url, .. } => Err(anyhow::anyhow!(
+            "Publishing to HTTP entries is not supported, upload manifest.toml and package.tar.gz to {} instead",
+            http_version_url(url, &package.version.to_string())
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_published_dependencies() {
+        let manifest = toml::from_str("[dependencies]\ncore = \"^1\"\n\n[dev-dependencies]\ntest = { path = \"../test\" }\n").unwrap();
+        check_published_dependencies(&manifest).unwrap();
+
+        for (dependency, key) in [
+            ("{ path = \"../core\" }", "path"),
+            ("{ git = \"https://example.com/core.git\", tag = \"1.0.0\" }", "git"),
+            ("{ version = \"^1\", registry = \"team\" }", "registry"),
+        ] {
+            let manifest = toml::from_str(&format!("[dependencies]\ncore = {dependency}\n")).unwrap();
+            let error = check_published_dependencies(&manifest).unwrap_err().to_string();
+            assert!(error.contains(&format!("Dependency 'core' sets '{key}'")), "{error}");
+        }
+    }
+}
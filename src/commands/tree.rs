@@ -0,0 +1,248 @@
+use crate::commands::install::Resolution;
+use anyhow::{anyhow, Error};
+use std::collections::{BTreeSet, HashSet};
+
+/// Key of the root manifest in the graph, package names are never empty
+pub(crate) const ROOT: &str = "";
+
+#[derive(Clone, Copy, Default, clap::ValueEnum)]
+pub enum TreeFormat {
+    #[default]
+    Text,
+    Dot,
+    Json,
+}
+
+#[derive(Default)]
+pub struct TreeOptions {
+    /// Show the packages which depend on this package instead of the dependencies of the root
+    pub invert: Option<String>,
+    /// Maximum depth below the starting node
+    pub depth: Option<usize>,
+    /// Expand packages which were already shown instead of marking them with `(*)`
+    pub no_dedupe: bool,
+    pub format: TreeFormat,
+}
+
+/// Edge from a package to one of its dependencies together with the requirement
+pub(crate) struct Edge {
+    pub from: String,
+    pub to: String,
+    pub req: semver::VersionReq,
+    /// Optional dependencies and constraints only restrict the version, the package is pulled
+    /// in by another edge
+    pub optional: bool,
+}
+
+/// Returns all edges of the resolved graph, starting at the root manifest
+pub(crate) fn dependency_edges(resolution: &Resolution) -> Result<Vec<Edge>, Error> {
+    let mut edges = Vec::new();
+    for (name, req) in &resolution.dependencies.required {
+        edges.push(Edge { from: ROOT.to_string(), to: name.clone(), req: req.clone(), optional: false });
+    }
+    for (name, version) in &resolution.packages {
+        let package = resolution.inventory.get_package(name, version)?;
+        for (dep_name, dependency) in &package.dependencies {
+            edges.push(Edge {
+                from: name.clone(),
+                to: dep_name.clone(),
+                req: dependency.version.clone(),
+                optional: dependency.optional,
+            });
+        }
+        for (dep_name, req) in &package.constraints {
+            edges.push(Edge { from: name.clone(), to: dep_name.clone(), req: req.clone(), optional: true });
+        }
+    }
+    Ok(edges)
+}
+
+struct Graph<'a, 'b> {
+    root_name: String,
+    resolution: &'a Resolution<'b>,
+    edges: Vec<Edge>,
+    inverted: bool,
+}
+
+impl Graph<'_, '_> {
+    /// Returns the edges which are followed from `node`, depending on the direction of the view
+    fn children(&self, node: &str) -> Vec<&Edge> {
+        let mut children: Vec<&Edge> = self
+            .edges
+            .iter()
+            .filter(|e| if self.inverted { e.to == node } else { e.from == node })
+            .collect();
+        children.sort_by(|a, b| self.child(a).cmp(self.child(b)));
+        children
+    }
+
+    fn child<'e>(&self, edge: &'e Edge) -> &'e str {
+        if self.inverted { &edge.from } else { &edge.to }
+    }
+
+    fn label(&self, node: &str) -> String {
+        if node == ROOT {
+            return self.root_name.clone();
+        }
+        let version = &self.resolution.packages[node];
+        match self.resolution.direct_source(node) {
+            Some(source) => format!("{node} {version} [{source}]"),
+            None => format!("{node} {version}"),
+        }
+    }
+
+    /// Shows the requirement of the edge together with the version chosen for it. In the
+    /// inverted view the child is the dependent package which declares the requirement.
+    fn edge_label(&self, edge: &Edge) -> String {
+        if self.inverted {
+            return format!("{} (requires {} {})", self.label(&edge.from), edge.to, edge.req);
+        }
+        let version = &self.resolution.packages[&edge.to];
+        match self.resolution.direct_source(&edge.to) {
+            Some(source) => format!("{} {} -> {version} [{source}]", edge.to, edge.req),
+            None => format!("{} {} -> {version}", edge.to, edge.req),
+        }
+    }
+
+    fn print_text(&self, start: &str, options: &TreeOptions) {
+        println!("{}", self.label(start));
+        let mut expanded = HashSet::new();
+        let mut ancestors = vec![start.to_string()];
+        self.print_children(start, "", options, &mut expanded, &mut ancestors);
+    }
+
+    fn print_children(
+        &self,
+        node: &str,
+        prefix: &str,
+        options: &TreeOptions,
+        expanded: &mut HashSet<String>,
+        ancestors: &mut Vec<String>,
+    ) {
+        if options.depth.is_some_and(|depth| ancestors.len() > depth) {
+            return;
+        }
+
+        let children = self.children(node);
+        for (i, edge) in children.iter().enumerate() {
+            let last = i == children.len() - 1;
+            let child = self.child(edge);
+            let has_children = !self.children(child).is_empty();
+
+            let mut line = format!("{prefix}{}{}", if last { "└── " } else { "├── " }, self.edge_label(edge));
+            let expand = if ancestors.iter().any(|a| a == child) {
+                line.push_str(" (cycle)");
+                false
+            } else if has_children && !options.no_dedupe && !expanded.insert(child.to_string()) {
+                line.push_str(" (*)");
+                false
+            } else {
+                true
+            };
+            println!("{line}");
+
+            if expand {
+                ancestors.push(child.to_string());
+                let child_prefix = format!("{prefix}{}", if last { "    " } else { "│   " });
+                self.print_children(child, &child_prefix, options, expanded, ancestors);
+                ancestors.pop();
+            }
+        }
+    }
+
+    /// Returns the edges which are reachable from `start` within the depth limit
+    fn reachable_edges(&self, start: &str, depth: Option<usize>) -> Vec<&Edge> {
+        let mut visited = HashSet::from([start.to_string()]);
+        let mut current = vec![start.to_string()];
+        let mut edges = Vec::new();
+        let mut level = 0;
+        while !current.is_empty() && depth.is_none_or(|depth| level < depth) {
+            let mut next = Vec::new();
+            for node in &current {
+                for edge in self.children(node) {
+                    edges.push(edge);
+                    let child = self.child(edge);
+                    if visited.insert(child.to_string()) {
+                        next.push(child.to_string());
+                    }
+                }
+            }
+            current = next;
+            level += 1;
+        }
+        edges
+    }
+
+    fn print_dot(&self, edges: &[&Edge]) {
+        let nodes: BTreeSet<&str> = edges.iter().flat_map(|e| [e.from.as_str(), e.to.as_str()]).collect();
+        println!("digraph dependencies {{");
+        for node in nodes {
+            println!("    \"{node}\" [label=\"{}\"];", self.label(node).replace('"', "\\\""));
+        }
+        for edge in edges {
+            println!("    \"{}\" -> \"{}\" [label=\"{}\"];", edge.from, edge.to, edge.req);
+        }
+        println!("}}");
+    }
+
+    fn print_json(&self, edges: &[&Edge]) -> Result<(), Error> {
+        let names: BTreeSet<&str> = edges.iter().flat_map(|e| [e.from.as_str(), e.to.as_str()]).collect();
+        let packages: Vec<_> = names
+            .into_iter()
+            .filter(|name| *name != ROOT)
+            .map(|name| {
+                serde_json::json!({
+                    "name": name,
+                    "version": self.resolution.packages[name].to_string(),
+                    "source": self.resolution.direct_source(name),
+                })
+            })
+            .collect();
+        let dependencies: Vec<_> = edges
+            .iter()
+            .map(|e| {
+                serde_json::json!({
+                    "from": if e.from == ROOT { &self.root_name } else { &e.from },
+                    "to": e.to,
+                    "req": e.req.to_string(),
+                })
+            })
+            .collect();
+        let output = serde_json::json!({
+            "root": self.root_name,
+            "packages": packages,
+            "dependencies": dependencies,
+        });
+        println!("{}", serde_json::to_string_pretty(&output)?);
+        Ok(())
+    }
+}
+
+/// Prints the resolved dependency graph. `root_name` is shown for the root manifest.
+pub fn tree(resolution: &Resolution, root_name: &str, options: &TreeOptions) -> Result<(), Error> {
+    let edges = dependency_edges(resolution)?.into_iter().filter(|e| !e.optional).collect();
+
+    let start = match &options.invert {
+        Some(name) if resolution.packages.contains_key(name) => name.as_str(),
+        Some(name) => return Err(anyhow!("Package '{name}' is not part of the dependency graph")),
+        None => ROOT,
+    };
+    let graph = Graph {
+        root_name: root_name.to_string(),
+        resolution,
+        edges,
+        inverted: options.invert.is_some(),
+    };
+
+    match options.format {
+        TreeFormat::Text => {
+            graph.print_text(start, options);
+            Ok(())
+        },
+        TreeFormat::Dot => {
+            graph.print_dot(&graph.reachable_edges(start, options.depth));
+            Ok(())
+        },
+        TreeFormat::Json => graph.print_json(&graph.reachable_edges(start, options.depth)),
+    }
+}
@@ -0,0 +1,131 @@
+use crate::commands::install::Resolution;
+use crate::commands::tree::{dependency_edges, Edge, ROOT};
+use anyhow::{anyhow, Error};
+
+/// Maximum number of newer versions which are explained
+const MAX_LISTED_VERSIONS: usize = 10;
+
+struct Why<'a, 'b> {
+    root_name: String,
+    resolution: &'a Resolution<'b>,
+    edges: Vec<Edge>,
+}
+
+impl Why<'_, '_> {
+    fn label(&self, node: &str) -> String {
+        if node == ROOT {
+            return self.root_name.clone();
+        }
+        format!("{node} {}", self.resolution.packages[node])
+    }
+
+    /// Collects all paths from `node` to `target` which do not visit a package twice
+    fn collect_paths<'e>(&'e self, node: &str, target: &str, path: &mut Vec<&'e Edge>, paths: &mut Vec<Vec<&'e Edge>>) {
+        for edge in self.edges.iter().filter(|e| e.from == node && !e.optional) {
+            if edge.to == ROOT || path.iter().any(|e| e.to == edge.to) {
+                continue;
+            }
+            path.push(edge);
+            if edge.to == target {
+                paths.push(path.clone());
+            } else {
+                self.collect_paths(&edge.to, target, path, paths);
+            }
+            path.pop();
+        }
+    }
+
+    /// Returns all requirements on `target` together with the package which declares them
+    fn requirements(&self, target: &str) -> Vec<(semver::VersionReq, String)> {
+        let mut requirements: Vec<_> = self
+            .edges
+            .iter()
+            .filter(|e| e.to == target)
+            .map(|e| {
+                let verb = if e.optional { "restricted" } else { "required" };
+                (e.req.clone(), format!("{verb} by {}", self.label(&e.from)))
+            })
+            .collect();
+        if let Some(req) = self.resolution.dependencies.constraints.get(target) {
+            requirements.push((req.clone(), format!("restricted by {}", self.root_name)));
+        }
+        requirements
+    }
+
+    /// Explains why no newer version than the selected one was chosen
+    fn print_selection(&self, target: &str) {
+        let version = &self.resolution.packages[target];
+        if let Some(source) = self.resolution.direct_source(target) {
+            println!("{target} {version} is taken from {source}");
+            return;
+        }
+
+        let requirements = self.requirements(target);
+        let mut newer: Vec<_> = self
+            .resolution
+            .inventory
+            .candidate_versions(target)
+            .into_iter()
+            .filter(|(v, _)| v > version)
+            .collect();
+        if newer.is_empty() {
+            println!("{target} {version} is the newest available version");
+            return;
+        }
+
+        // The closest newer version shows which requirement holds the package back
+        newer.reverse();
+        println!("{target} {version} was selected because newer versions are not allowed:");
+        for (newer_version, reason) in newer.iter().take(MAX_LISTED_VERSIONS) {
+            // Requirements are named first, installed packages are kept at their version anyway
+            let reason = requirements
+                .iter()
+                .find(|(req, _)| !req.matches(newer_version))
+                .map(|(req, source)| format!("does not match {req} {source}"))
+                .or_else(|| reason.clone())
+                .unwrap_or("conflicts with other packages in the graph".to_string());
+            println!("  {newer_version}: {reason}");
+        }
+        if newer.len() > MAX_LISTED_VERSIONS {
+            println!("  ... and {} newer versions", newer.len() - MAX_LISTED_VERSIONS);
+        }
+    }
+}
+
+/// Explains why `package` is part of the resolved graph and why its version was selected
+pub fn why(resolution: &Resolution, root_name: &str, package: &str) -> Result<(), Error> {
+    if !resolution.packages.contains_key(package) {
+        return Err(anyhow!("Package '{package}' is not part of the dependency graph"));
+    }
+    let why = Why {
+        root_name: root_name.to_string(),
+        resolution,
+        edges: dependency_edges(resolution)?,
+    };
+
+    let mut paths = Vec::new();
+    why.collect_paths(ROOT, package, &mut Vec::new(), &mut paths);
+    let mut lines: Vec<_> = paths
+        .iter()
+        .map(|path| {
+            let mut line = why.root_name.clone();
+            for edge in path {
+                line.push_str(&format!(" -> {} {} ({})", edge.to, edge.req, resolution.packages[&edge.to]));
+            }
+            line
+        })
+        .collect();
+    lines.sort_by_key(|line| (line.len(), line.clone()));
+    println!("{} is included through:", why.label(package));
+    for line in lines {
+        println!("  {line}");
+    }
+
+    println!("Requirements on {package}:");
+    for (req, source) in why.requirements(package) {
+        println!("  {req} {source}");
+    }
+
+    why.print_selection(package);
+    Ok(())
+}
@@ -1,18 +1,44 @@
 use anyhow::Error;
-use git2::{Repository, build::RepoBuilder};
+use git2::{AutotagOption, FetchOptions, Repository, build::RepoBuilder};
+use metabuild_resolver::cache::Cache;
+use metabuild_resolver::repository::BareRepository;
 use std::path::Path;
-use metabuild_git::*;
 
-pub fn clone(
+/// Checks whether the cached bare repository for `url` contains the given revision
+pub fn has_cached_revision(url: &str, revision: &str, cache: &Cache) -> bool {
+    Repository::open_bare(cache.git_path(url))
+        .map(|repo| repo.revparse_single(revision).is_ok())
+        .unwrap_or(false)
+}
+
+/// Clones the repository at `url` through the package cache. The cached bare repository is
+/// only fetched if it does not contain the requested revision yet.
+pub fn clone_cached(
     url: &str,
+    revision: &str,
+    cache: &Cache,
+    offline: bool,
     dst: &Path,
 ) -> Result<Repository, Error> {
-    let git_config = make_git_config()?;
-    let auth = make_git_authenticator();
-    let fetch_options = make_fetch_options(&auth, &git_config);
+    let cache_path = cache.git_path(url);
+    if !has_cached_revision(url, revision, cache) {
+        if offline {
+            return Err(anyhow::anyhow!("Revision {revision} of {url} is not available in the package cache"));
+        }
+        BareRepository::new(url, Some(cache_path.as_path()))?;
+    }
+
+    let mut fetch_options = FetchOptions::new();
+    fetch_options.download_tags(AutotagOption::All);
     let mut repo_builder = RepoBuilder::new();
     repo_builder.fetch_options(fetch_options);
-    Ok(repo_builder.clone(url, dst)?)
+    let cache_url = cache_path
+        .to_str()
+        .ok_or(anyhow::anyhow!("Invalid cache path {:?}", cache_path))?;
+    let repo = repo_builder.clone(cache_url, dst)?;
+    repo.remote_set_url("origin", url)?;
+    cache.mark_git_used(url);
+    Ok(repo)
 }
 
 pub fn checkout(
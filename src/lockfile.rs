@@ -0,0 +1,328 @@
+use anyhow::{anyhow, Error};
+use metabuild_resolver::index::Entry;
+use metabuild_resolver::manifest::{Dependency, Patch};
+use metabuild_resolver::package;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+const LOCKFILE_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum LockedSource {
+    Git {
+        url: String,
+        commit: String,
+    },
+    Artifactory {
+        server: String,
+        repo: String,
+        path: String,
+        sha256: String,
+    },
+    /// Package from a static web server, the url is the template of the index entry
+    Http {
+        url: String,
+        sha256: String,
+    },
+    /// Package from a local package directory, the digest is only recorded for tarballs
+    Local {
+        path: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        sha256: Option<String>,
+    },
+    /// Local package, the path is relative to the manifest
+    Path {
+        path: String,
+    },
+}
+
+impl LockedSource {
+    /// Returns true if this source refers to the same location as the index entry,
+    /// regardless of the recorded commit or digest
+    pub fn matches_entry(&self, entry: &Entry) -> bool {
+        match (self, entry) {
+            (LockedSource::Git { url, .. }, Entry::Git { url: entry_url }) => url == entry_url,
+            (
+                LockedSource::Artifactory { server, repo, path, .. },
+                Entry::Artifactory { server: entry_server, repo: entry_repo, path: entry_path },
+            ) => server == entry_server && repo == entry_repo && path == entry_path,
+            (LockedSource::Http { url, .. }, Entry::Http { url: entry_url, .. }) => url == entry_url,
+            (LockedSource::Local { path, .. }, Entry::Local { path: entry_path }) => path == entry_path,
+            _ => false,
+        }
+    }
+
+    /// Returns the recorded digest of the package tarball
+    pub fn sha256(&self) -> Option<&str> {
+        match self {
+            LockedSource::Artifactory { sha256, .. } | LockedSource::Http { sha256, .. } => Some(sha256),
+            LockedSource::Local { sha256, .. } => sha256.as_deref(),
+            LockedSource::Git { .. } | LockedSource::Path { .. } => None,
+        }
+    }
+}
+
+/// Formats a dependency like it is written in the manifest
+fn describe(dependency: &Dependency) -> String {
+    toml::Value::try_from(dependency).map_or_else(|_| dependency.version.to_string(), |value| value.to_string())
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LockedPackage {
+    pub name: String,
+    pub version: semver::Version,
+    pub source: LockedSource,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Lockfile {
+    pub version: u32,
+    /// Dependencies of the manifest the lockfile was generated from, including their sources
+    #[serde(default)]
+    pub dependencies: BTreeMap<String, Dependency>,
+    /// `[patch]` table of the manifest the lockfile was generated from
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub patch: BTreeMap<String, Patch>,
+    #[serde(default, rename = "package")]
+    pub packages: Vec<LockedPackage>,
+}
+
+impl Lockfile {
+    pub fn new(
+        dependencies: &BTreeMap<String, Dependency>,
+        patch: &BTreeMap<String, Patch>,
+        mut packages: Vec<LockedPackage>,
+    ) -> Self {
+        packages.sort_by(|a, b| a.name.cmp(&b.name));
+        Self {
+            version: LOCKFILE_VERSION,
+            dependencies: dependencies.clone(),
+            patch: patch.clone(),
+            packages,
+        }
+    }
+
+    pub fn read(path: &Path) -> Result<Self, Error> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| anyhow!("Could not read lockfile '{}': {e}", path.display()))?;
+        let lockfile: Lockfile = toml::from_str(&content)
+            .map_err(|e| anyhow!("Could not parse lockfile '{}': {e}", path.display()))?;
+        if lockfile.version != LOCKFILE_VERSION {
+            return Err(anyhow!(
+                "Unsupported lockfile version {} in '{}'",
+                lockfile.version,
+                path.display()
+            ));
+        }
+        Ok(lockfile)
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), Error> {
+        let content = toml::to_string_pretty(self)?;
+        std::fs::write(
+            path,
+            format!("# This file is generated by metabuild. Do not edit it manually.\n\n{content}"),
+        )
+        .map_err(|e| anyhow!("Could not write lockfile '{}': {e}", path.display()))
+    }
+
+    pub fn get(&self, name: &str) -> Option<&LockedPackage> {
+        self.packages.iter().find(|p| p.name == name)
+    }
+
+    /// Checks that the lockfile was generated from the given dependencies and patches of the
+    /// manifest and that the locked versions still satisfy them. Pre-release versions match like
+    /// in the resolver, i.e. with `pre` (`--pre`) for all dependencies.
+    pub fn verify(
+        &self,
+        dependencies: &BTreeMap<String, Dependency>,
+        patch: &BTreeMap<String, Patch>,
+        pre: bool,
+    ) -> Result<(), Error> {
+        let mut problems = Vec::new();
+
+        for (name, dependency) in dependencies {
+            let req = &dependency.version;
+            match self.dependencies.get(name) {
+                Some(locked) if locked == dependency => {}
+                Some(locked) if Dependency { version: req.clone(), ..locked.clone() } == *dependency => problems.push(
+                    format!("requirement for '{name}' changed from '{}' to '{req}'", locked.version),
+                ),
+                Some(locked) => problems.push(format!(
+                    "dependency '{name}' changed from {} to {}",
+                    describe(locked),
+                    describe(dependency)
+                )),
+                None => problems.push(format!("dependency '{name}' is not in the lockfile")),
+            }
+
+            // Path and git dependencies are taken as they are, e.g. with version `1.0.0-dev`
+            let direct = dependency.path.is_some() || dependency.git.is_some();
+            let locked_req = package::VersionReq::new(req.clone(), pre || dependency.pre || direct);
+            match self.get(name) {
+                Some(package) if !locked_req.matches(&package::Version(package.version.clone())) => problems.push(format!(
+                    "locked version {} of '{name}' does not match '{req}'",
+                    package.version
+                )),
+                Some(_) => {}
+                // Optional dependencies are only locked if another package requires them
+                None if dependency.optional => {}
+                None => problems.push(format!("no locked package for '{name}'")),
+            }
+        }
+
+        for name in self.dependencies.keys() {
+            if !dependencies.contains_key(name) {
+                problems.push(format!("dependency '{name}' was removed from the manifest"));
+            }
+        }
+
+        for (name, replacement) in patch {
+            match self.patch.get(name) {
+                Some(locked) if locked == replacement => {}
+                Some(locked) => problems.push(format!("patch for '{name}' changed from {locked} to {replacement}")),
+                None => problems.push(format!("patch for '{name}' is not in the lockfile")),
+            }
+        }
+        for name in self.patch.keys() {
+            if !patch.contains_key(name) {
+                problems.push(format!("patch for '{name}' was removed from the manifest"));
+            }
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow!(
+                "The lockfile is out of date:\n{}\nRun 'mb install' without --locked to update it.",
+                problems.iter().map(|p| format!("  - {p}")).collect::<Vec<_>>().join("\n")
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn git_package(name: &str, version: &str) -> LockedPackage {
+        LockedPackage {
+            name: name.to_string(),
+            version: semver::Version::parse(version).unwrap(),
+            source: LockedSource::Git { url: format!("https://example.com/{name}.git"), commit: "0".repeat(40) },
+        }
+    }
+
+    /// Parses dependencies in the manifest syntax, e.g. `a = "^1.0"`
+    fn dependencies(content: &str) -> BTreeMap<String, Dependency> {
+        toml::from_str(content).unwrap()
+    }
+
+    #[test]
+    fn test_verify() {
+        let manifest = dependencies("a = \"^1.0\"\nb = \"^2.0\"");
+        let no_patch = BTreeMap::new();
+        let lockfile = Lockfile::new(&manifest, &no_patch, vec![git_package("b", "2.1.0"), git_package("a", "1.2.0")]);
+        assert_eq!(lockfile.packages[0].name, "a");
+        lockfile.verify(&manifest, &no_patch, false).unwrap();
+
+        let error = lockfile
+            .verify(&dependencies("a = \"^1.3\"\nb = \"^2.0\""), &no_patch, false)
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("requirement for 'a' changed from '^1.0' to '^1.3'"), "{error}");
+
+        let error = lockfile.verify(&dependencies("a = \"^1.0\""), &no_patch, false).unwrap_err().to_string();
+        assert!(error.contains("dependency 'b' was removed from the manifest"), "{error}");
+
+        let error = lockfile
+            .verify(&dependencies("a = \"^1.0\"\nb = \"^2.0\"\nc = \"*\""), &no_patch, false)
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("dependency 'c' is not in the lockfile"), "{error}");
+        assert!(error.contains("no locked package for 'c'"), "{error}");
+
+        // A lockfile whose versions do not match its own requirements is rejected as well
+        let lockfile = Lockfile::new(&manifest, &no_patch, vec![git_package("a", "2.0.0"), git_package("b", "2.1.0")]);
+        let error = lockfile.verify(&manifest, &no_patch, false).unwrap_err().to_string();
+        assert!(error.contains("locked version 2.0.0 of 'a' does not match '^1.0'"), "{error}");
+    }
+
+    #[test]
+    fn test_verify_prerelease() {
+        let no_patch = BTreeMap::new();
+        let manifest = dependencies("a = { version = \"^1.0\", pre = true }");
+        let lockfile = Lockfile::new(&manifest, &no_patch, vec![git_package("a", "1.1.0-beta.1")]);
+        lockfile.verify(&manifest, &no_patch, false).unwrap();
+
+        // Selected with --pre
+        let manifest = dependencies("a = \"^1.0\"");
+        let lockfile = Lockfile::new(&manifest, &no_patch, vec![git_package("a", "1.1.0-beta.1")]);
+        lockfile.verify(&manifest, &no_patch, true).unwrap();
+        let error = lockfile.verify(&manifest, &no_patch, false).unwrap_err().to_string();
+        assert!(error.contains("locked version 1.1.0-beta.1 of 'a' does not match '^1.0'"), "{error}");
+
+        let manifest = dependencies("a = { path = \"../a\" }");
+        let lockfile = Lockfile::new(&manifest, &no_patch, vec![git_package("a", "1.0.0-dev")]);
+        lockfile.verify(&manifest, &no_patch, false).unwrap();
+    }
+
+    #[test]
+    fn test_verify_sources() {
+        let manifest = dependencies(
+            r#"
+            a = { git = "https://example.com/a.git", tag = "1.0.0" }
+            b = { path = "../b" }
+            c = { version = "^1", registry = "team" }
+            "#,
+        );
+        let patch: BTreeMap<String, Patch> = toml::from_str(r#"d = { package = "d-fork" }"#).unwrap();
+        let packages = vec![git_package("a", "1.0.0"), git_package("b", "0.1.0"), git_package("c", "1.0.0")];
+        let lockfile = Lockfile::new(&manifest, &patch, packages);
+        lockfile.verify(&manifest, &patch, false).unwrap();
+
+        // Changes of the source are detected although the version requirement stays the same
+        let changed = dependencies(
+            r#"
+            a = { git = "https://example.com/a.git", tag = "1.1.0" }
+            b = { path = "../other-b" }
+            c = "^1"
+            "#,
+        );
+        let error = lockfile.verify(&changed, &patch, false).unwrap_err().to_string();
+        assert!(error.contains("dependency 'a' changed from"), "{error}");
+        assert!(error.contains("tag = \"1.1.0\""), "{error}");
+        assert!(error.contains("dependency 'b' changed from"), "{error}");
+        assert!(error.contains("dependency 'c' changed from"), "{error}");
+
+        let other_patch: BTreeMap<String, Patch> = toml::from_str(r#"d = { path = "../d" }"#).unwrap();
+        let error = lockfile.verify(&manifest, &other_patch, false).unwrap_err().to_string();
+        assert!(error.contains("patch for 'd' changed from index entry 'd-fork' to path ../d"), "{error}");
+        let error = lockfile.verify(&manifest, &BTreeMap::new(), false).unwrap_err().to_string();
+        assert!(error.contains("patch for 'd' was removed from the manifest"), "{error}");
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("manifest.lock");
+        let manifest = dependencies(
+            r#"
+            a = "^1.0"
+            b = { git = "https://example.com/b.git", branch = "develop" }
+            "#,
+        );
+        let patch: BTreeMap<String, Patch> =
+            toml::from_str(r#"c = { package = "c-fork", registry = "team" }"#).unwrap();
+        let packages = vec![git_package("a", "1.2.0"), git_package("b", "0.3.0")];
+        Lockfile::new(&manifest, &patch, packages).write(&path).unwrap();
+
+        let lockfile = Lockfile::read(&path).unwrap();
+        assert_eq!(lockfile.get("a"), Some(&git_package("a", "1.2.0")));
+        assert_eq!(lockfile.dependencies, manifest);
+        assert_eq!(lockfile.patch, patch);
+        lockfile.verify(&manifest, &patch, false).unwrap();
+    }
+}
@@ -3,15 +3,19 @@ use clap::Parser;
 use colored::Colorize;
 use color_eyre::eyre::Result;
 use commands::config::ConfigData;
+use commands::outdated::OutputFormat;
 use log::{error, info, debug};
 use md5;
-use metabuild_resolver::index::Index;
+use metabuild_resolver::cache::Cache;
+use metabuild_resolver::index::{Index, Registries, DEFAULT_REGISTRY};
+use metabuild_resolver::SolverError;
 use std::panic;
 use std::path::{Path, PathBuf};
 
 mod cli;
 mod commands;
 mod git;
+mod lockfile;
 mod net;
 mod pinning;
 mod scripting;
@@ -29,20 +33,40 @@ fn parse_manifest(file: &Path) -> toml::Table {
         .expect(format!("Could not parse manifest file '{}'", file.display()).as_str())
 }
 
-fn get_index_url(config: &ConfigData, manifest: &toml::Table) -> Result<String, Error> {
-    let url = manifest.get("registries")
-        .and_then(toml::Value::as_table)
-        .and_then(|registries| registries.get("default"))
-        .and_then(toml::Value::as_str)
-        .or(config.index.as_ref().map(String::as_str))
-        .expect("No index URL specified in project or global config")
-        .to_string();
-
-    if url.starts_with("http") {
-        return Err(anyhow::anyhow!("HTTP(S) index URLs are currently not supported. Use SSH instead."));
+/// Returns the index URLs of all registries in the manifest, the default registry falls back to
+/// the index from the config
+fn get_registry_urls(config: &ConfigData, manifest: &toml::Table) -> Result<Vec<(String, String)>, Error> {
+    let mut urls = Vec::new();
+    if let Some(registries) = manifest.get("registries") {
+        let registries = registries
+            .as_table()
+            .ok_or(anyhow::anyhow!("[registries] must be a table"))?;
+        for (name, url) in registries {
+            let url = url
+                .as_str()
+                .ok_or(anyhow::anyhow!("Registry '{name}' in [registries] must be an index URL"))?;
+            urls.push((name.clone(), url.to_string()));
+        }
+    }
+
+    if !urls.iter().any(|(name, _)| name == DEFAULT_REGISTRY) {
+        if let Some(url) = &config.index {
+            urls.push((DEFAULT_REGISTRY.to_string(), url.clone()));
+        }
     }
+    if urls.is_empty() {
+        return Err(anyhow::anyhow!("No index URL specified in project or global config"));
+    }
+
+    Ok(urls)
+}
 
-    Ok(url)
+fn get_index_url(config: &ConfigData, manifest: &toml::Table, registry: &str) -> Result<String, Error> {
+    get_registry_urls(config, manifest)?
+        .into_iter()
+        .find(|(name, _)| name == registry)
+        .map(|(_, url)| url)
+        .ok_or(anyhow::anyhow!("No index URL specified for registry '{registry}'"))
 }
 
 fn open_index(index_url: &str, index_path: &Path) -> Result<Index, Error> {
@@ -55,6 +79,82 @@ fn open_index(index_url: &str, index_path: &Path) -> Result<Index, Error> {
     Ok(index)
 }
 
+fn open_local_index(index_url: &str, index_path: &Path) -> Result<Index, Error> {
+    Index::open_local(index_url, "main", index_path)
+        .map_err(|e| anyhow::anyhow!("Index {index_url} is not available offline: {e}"))
+}
+
+/// Opens all registries of the manifest, only from the local copies in offline mode
+fn open_manifest_registries(config: &ConfigData, manifest: &toml::Table, local_path: &Path, offline: bool) -> Result<Registries, Error> {
+    let mut registries = Registries::new();
+    for (name, index_url) in get_registry_urls(config, manifest)? {
+        let index_hash = format!("{:x}", md5::compute(&index_url));
+        let index_path = local_path.join("index").join(index_hash);
+        let index = if offline {
+            open_local_index(&index_url, &index_path)?
+        } else {
+            open_index(&index_url, &index_path)?
+        };
+        registries.add(&name, index);
+    }
+
+    Ok(registries)
+}
+
+fn open_cache() -> Result<Cache, Error> {
+    let home_dir = dirs::home_dir()
+        .ok_or(anyhow::anyhow!("Could not determine the home directory for the package cache"))?;
+    Cache::new(&home_dir.join(".mb").join("cache"))
+}
+
+/// Manifest and registries of a project for the commands which inspect its dependency graph
+struct Project {
+    manifest_path: PathBuf,
+    manifest: toml::Table,
+    registries: Registries,
+    cache: Cache,
+    options: commands::install::InstallOptions,
+}
+
+impl Project {
+    fn open(config: &ConfigData, file: &Option<PathBuf>, offline: bool, local_path: &Path) -> Result<Self, Error> {
+        let options = commands::install::InstallOptions {
+            offline: offline || config.offline.unwrap_or(false),
+            quiet: true,
+            ..Default::default()
+        };
+        let manifest_path = file.clone().unwrap_or(PathBuf::from("manifest.toml"));
+        let manifest = parse_manifest(&manifest_path);
+        let registries = open_manifest_registries(config, &manifest, local_path, options.offline)?;
+        let cache = open_cache()?;
+        Ok(Self { manifest_path, manifest, registries, cache, options })
+    }
+
+    fn lockfile_path(&self) -> PathBuf {
+        self.manifest_path.with_file_name("manifest.lock")
+    }
+
+    /// Name which is shown for the root manifest
+    fn root_name(&self) -> &str {
+        self.manifest
+            .get("package")
+            .and_then(|p| p.get("name"))
+            .and_then(toml::Value::as_str)
+            .unwrap_or("(root)")
+    }
+
+    fn resolver<'a>(&'a self, config: &'a ConfigData, local_path: &Path) -> Result<commands::install::Resolver<'a>, Error> {
+        let manifest_dir = self.manifest_path.parent().unwrap_or(Path::new("."));
+        commands::install::Resolver::new(&self.registries, config, &self.manifest, manifest_dir, local_path, &self.cache, &self.options)
+    }
+
+    /// Resolves the graph of the installed packages, see `commands::install::resolve_installed`
+    fn resolve_installed<'a>(&'a self, config: &'a ConfigData, local_path: &Path) -> Result<commands::install::Resolution<'a>, Error> {
+        let resolver = self.resolver(config, local_path)?;
+        commands::install::resolve_installed(resolver, &self.lockfile_path(), local_path, &self.options)
+    }
+}
+
 fn to_scope(local: bool) -> commands::config::ConfigScope {
     if local {
         commands::config::ConfigScope::Local
@@ -63,6 +163,17 @@ fn to_scope(local: bool) -> commands::config::ConfigScope {
     }
 }
 
+/// Prints the conflict report as JSON and exits if the dependencies could not be resolved
+fn report_conflicts(result: Result<(), Error>, format: OutputFormat) -> Result<(), Error> {
+    if let (Err(e), OutputFormat::Json) = (&result, format) {
+        if let Some(SolverError::Unsolvable(report)) = e.downcast_ref::<SolverError>() {
+            println!("{}", serde_json::to_string_pretty(report)?);
+            std::process::exit(1);
+        }
+    }
+    result
+}
+
 pub fn main() -> Result<(), anyhow::Error> {
     color_eyre::install().map_err(|_| anyhow::anyhow!("Failed to install color_eyre"))?;
     pretty_env_logger::init();
@@ -88,6 +199,9 @@ pub fn main() -> Result<(), anyhow::Error> {
     let cli = Cli::parse();
     let mut config_figment = commands::config::Config::new();
     let config = &config_figment.merged;
+    for (host, token) in &config.git_token {
+        metabuild_git::add_token(host, token);
+    }
 
     let local_path = Path::new(".mb");
     
@@ -112,12 +226,24 @@ pub fn main() -> Result<(), anyhow::Error> {
             IndexCommands::AddArtifactory { name, server, repo, path, index } => {
                 handle_index_command(index, &|index| commands::index::add_artifactory(index, name, server, repo, path))
             }
+            IndexCommands::AddHttp { name, url, listing, index } => {
+                handle_index_command(index, &|index| commands::index::add_http(index, name, url, *listing))
+            }
+            IndexCommands::AddLocal { name, path, index } => {
+                handle_index_command(index, &|index| commands::index::add_local(index, name, path))
+            }
             IndexCommands::Remove { name, index } => {
                 handle_index_command(index, &|index| commands::index::remove(index, name))
             }
             IndexCommands::Push { index } => {
                 handle_index_command(index, &|index| commands::index::push(index))
             }
+            IndexCommands::Yank { name, version, index } => {
+                handle_index_command(index, &|index| commands::index::yank(index, name, version))
+            }
+            IndexCommands::Unyank { name, version, index } => {
+                handle_index_command(index, &|index| commands::index::unyank(index, name, version))
+            }
             IndexCommands::Revert { index } => {
                 handle_index_command(index, &|index| commands::index::revert(index))
             }
@@ -127,13 +253,96 @@ pub fn main() -> Result<(), anyhow::Error> {
         },
         Commands::Install {
             file,
+            locked,
+            offline,
+            resolution,
+            pre,
+            error_format,
         } => {
-            let manifest = parse_manifest(file.as_ref().unwrap_or(&PathBuf::from("manifest.toml")));
-            let index_url: String = get_index_url(&config, &manifest)?;
+            let options = commands::install::InstallOptions {
+                offline: *offline || config.offline.unwrap_or(false),
+                // Only the conflict report is printed to stdout for JSON output
+                quiet: matches!(error_format, OutputFormat::Json),
+                resolution: *resolution,
+                pre: *pre,
+            };
+            let manifest_path = file.clone().unwrap_or(PathBuf::from("manifest.toml"));
+            let manifest = parse_manifest(&manifest_path);
+            let lockfile_path = manifest_path.with_file_name("manifest.lock");
+            let cache = open_cache()?;
+            if *locked {
+                // The index is only needed for warnings about yanked and deprecated versions
+                let registries = open_manifest_registries(config, &manifest, local_path, options.offline)
+                    .inspect_err(|e| debug!("Not checking the locked versions against the index: {e}"))
+                    .ok();
+                return commands::install::install_locked_dependencies(registries.as_ref(), config, &manifest, &lockfile_path, local_path, &cache, &options);
+            }
+
+            let registries = open_manifest_registries(config, &manifest, local_path, options.offline)?;
+            let result = commands::install::install_dependencies(&registries, config, &manifest, &lockfile_path, local_path, &cache, &options);
+            report_conflicts(result, *error_format)
+        }
+        Commands::Cache { command } => {
+            let cache = open_cache()?;
+            match command {
+                CacheCommands::Info => commands::cache::info(&cache),
+                CacheCommands::Clean => commands::cache::clean(&cache),
+                CacheCommands::Prune { days } => commands::cache::prune(&cache, *days),
+            }
+        }
+        Commands::Tree { file, invert, depth, no_dedupe, format, offline } => {
+            let project = Project::open(config, file, *offline, local_path)?;
+            let resolution = project.resolve_installed(config, local_path)?;
+            let tree_options = commands::tree::TreeOptions {
+                invert: invert.clone(),
+                depth: *depth,
+                no_dedupe: *no_dedupe,
+                format: *format,
+            };
+            commands::tree::tree(&resolution, project.root_name(), &tree_options)
+        }
+        Commands::Why { package, file, offline } => {
+            let project = Project::open(config, file, *offline, local_path)?;
+            let resolution = project.resolve_installed(config, local_path)?;
+            commands::why::why(&resolution, project.root_name(), package)
+        }
+        Commands::Outdated { file, format, exit_code, offline } => {
+            let project = Project::open(config, file, *offline, local_path)?;
+            let lockfile_path = project.lockfile_path();
+            let lockfile = lockfile_path.exists().then(|| lockfile::Lockfile::read(&lockfile_path)).transpose()?;
+            let resolution = project.resolver(config, local_path)?.solve(&project.options)?;
+            let found = commands::outdated::outdated(&resolution, lockfile.as_ref(), *format)?;
+            if found && *exit_code {
+                std::process::exit(1);
+            }
+            Ok(())
+        }
+        Commands::Update { packages, recursive, pre, file, offline, error_format } => {
+            let options = commands::install::InstallOptions {
+                offline: *offline || config.offline.unwrap_or(false),
+                quiet: matches!(error_format, OutputFormat::Json),
+                pre: *pre,
+                ..Default::default()
+            };
+            let manifest_path = file.clone().unwrap_or(PathBuf::from("manifest.toml"));
+            let manifest = parse_manifest(&manifest_path);
+            let manifest_dir = manifest_path.parent().unwrap_or(Path::new("."));
+            let lockfile_path = manifest_path.with_file_name("manifest.lock");
+            let registries = open_manifest_registries(config, &manifest, local_path, options.offline)?;
+            let cache = open_cache()?;
+            let resolver = commands::install::Resolver::new(&registries, config, &manifest, manifest_dir, local_path, &cache, &options)?;
+            let update = commands::install::UpdateOptions { packages, recursive: *recursive };
+            let result = commands::install::update_dependencies(resolver, config, &lockfile_path, local_path, &cache, &update, &options);
+            report_conflicts(result, *error_format)
+        }
+        Commands::Publish { file, force, registry } => {
+            let manifest_path = file.clone().unwrap_or(PathBuf::from("manifest.toml"));
+            let manifest = parse_manifest(&manifest_path);
+            let index_url: String = get_index_url(config, &manifest, registry)?;
             let index_hash = format!("{:x}", md5::compute(&index_url));
             let index_path = local_path.join("index").join(index_hash);
             let index = open_index(&index_url, &index_path)?;
-            commands::install::install_dependencies(&index, &config, &manifest, local_path)
+            commands::publish::publish(&index, config, &manifest, &manifest_path, *force)
         }
         Commands::Run { file, args: _ } => {
             if let Err(e) = scripting::run_file(file.as_ref().unwrap_or(&PathBuf::from("main.koto"))) {
@@ -142,8 +351,8 @@ pub fn main() -> Result<(), anyhow::Error> {
                 Ok(())
             }
         }
-        Commands::Update => {
-            commands::update::update()
+        Commands::SelfCommand { command } => match command {
+            SelfCommands::Update => commands::update::update(),
         },
         Commands::Config { command } => match command {
             ConfigCommands::Set { key, value, local } => config_figment.set(key, value, to_scope(*local)),
@@ -164,6 +373,13 @@ pub fn main() -> Result<(), anyhow::Error> {
                 })
             },
             ConfigCommands::RemoveToken { server, local } => config_figment.remove_token(server, to_scope(*local)),
+            ConfigCommands::SetGitToken { host, token, local } => config_figment.set_git_token(host, token, to_scope(*local)),
+            ConfigCommands::GetGitToken { host } => {
+                config_figment.get_git_token(host).map(|v| {
+                    println!("{}", v);
+                })
+            },
+            ConfigCommands::RemoveGitToken { host, local } => config_figment.remove_git_token(host, to_scope(*local)),
         },
     }
 }